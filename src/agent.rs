@@ -0,0 +1,229 @@
+use std::collections::VecDeque;
+#[cfg(feature = "mlp")]
+use std::rc::Rc;
+
+use crate::game::{Action, Game, Plan, SolverLimit};
+use crate::mcts::{escape_policy, random_action, HeuristicEvaluator, Reward, MCTS};
+#[cfg(feature = "mlp")]
+use crate::mlp::Mlp;
+use crate::player::escape_bot_action;
+
+// Anything that can play the game, from hand-coded bots to searches
+pub trait Agent {
+    fn act(&mut self, game: &Game) -> Action;
+
+    // Called with each action once it has been taken, and the game it led to
+    fn observe(&mut self, _action: &Action, _game: &Game) {}
+
+    // Called before each new game
+    fn reset(&mut self) {}
+}
+
+// Plain functions like escape_bot_action and random_action are stateless agents
+impl<F: FnMut(&Game) -> Action> Agent for F {
+    fn act(&mut self, game: &Game) -> Action {
+        self(game)
+    }
+}
+
+// Searches each decision with a fresh MCTS
+pub struct MctsAgent {
+    playouts: usize,
+    new_mcts: Box<dyn FnMut() -> MCTS>,
+}
+
+impl MctsAgent {
+    pub fn new(playouts: usize, new_mcts: impl FnMut() -> MCTS + 'static) -> MctsAgent {
+        MctsAgent {
+            playouts,
+            new_mcts: Box::new(new_mcts),
+        }
+    }
+}
+
+impl Agent for MctsAgent {
+    fn act(&mut self, game: &Game) -> Action {
+        let mut mcts = (self.new_mcts)();
+        mcts.search(game, self.playouts);
+        mcts.best_action(game)
+    }
+}
+
+// Plays out a deterministic win whenever the solver finds one, and otherwise defers to
+// another agent
+pub struct SolverFirstAgent {
+    inner: Box<dyn Agent>,
+    limit: SolverLimit,
+    plan: VecDeque<Action>,
+}
+
+impl SolverFirstAgent {
+    pub fn new(inner: Box<dyn Agent>, limit: SolverLimit) -> SolverFirstAgent {
+        SolverFirstAgent {
+            inner,
+            limit,
+            plan: VecDeque::new(),
+        }
+    }
+}
+
+impl Agent for SolverFirstAgent {
+    fn act(&mut self, game: &Game) -> Action {
+        if let Some(action) = self.plan.front() {
+            if game.actions().contains(action) {
+                return *action;
+            }
+            self.plan.clear();
+        }
+        // Like the other tools, only look for lethal at the start of a turn
+        if game.turn_is_fresh() && game.fish.is_empty() {
+            if let Plan::Win(plays) = game.find_deterministic_win_within(self.limit) {
+                self.plan = plays.into_iter().map(Action::Play).collect();
+                if let Some(action) = self.plan.front() {
+                    return *action;
+                }
+            }
+        }
+        self.inner.act(game)
+    }
+
+    fn observe(&mut self, action: &Action, game: &Game) {
+        // Drop the plan if something else happened
+        if self.plan.front() == Some(action) {
+            self.plan.pop_front();
+        } else {
+            self.plan.clear();
+        }
+        self.inner.observe(action, game);
+    }
+
+    fn reset(&mut self) {
+        self.plan.clear();
+        self.inner.reset();
+    }
+}
+
+// How many playouts the named MCTS agents search with
+pub const DEFAULT_PLAYOUTS: usize = 200;
+
+// How hard the solver-first agents look for a win at the start of each turn
+const SOLVER_LIMIT: SolverLimit = SolverLimit::Nodes(500_000);
+
+// The names agent_from_name understands, for help text
+pub const AGENT_NAMES: &str = "escape, random, mcts[:objective], heuristic[:objective], \
+mlp:<path>, or solver-<agent> for any of these";
+
+// Builds an agent from a name like "escape", "mcts:win-by-6", or "solver-heuristic".
+// MCTS agents search with escape_policy as the prior, and the objective defaults to mean-turn.
+pub fn agent_from_name(name: &str) -> Result<Box<dyn Agent>, String> {
+    if let Some(inner) = name.strip_prefix("solver-") {
+        let inner = agent_from_name(inner)?;
+        return Ok(Box::new(SolverFirstAgent::new(inner, SOLVER_LIMIT)));
+    }
+    let (kind, arg) = match name.split_once(':') {
+        Some((kind, arg)) => (kind, Some(arg)),
+        None => (name, None),
+    };
+    let reward = || match arg {
+        Some(arg) => Reward::from_name(arg).ok_or(format!("unknown objective: {}", arg)),
+        None => Ok(Reward::MeanTurn),
+    };
+    match kind {
+        "escape" => Ok(Box::new(escape_bot_action)),
+        "random" => Ok(Box::new(random_action)),
+        "mcts" => {
+            let reward = reward()?;
+            Ok(Box::new(MctsAgent::new(DEFAULT_PLAYOUTS, move || {
+                MCTS::new(escape_policy).with_reward(reward)
+            })))
+        }
+        "heuristic" => {
+            let reward = reward()?;
+            Ok(Box::new(MctsAgent::new(DEFAULT_PLAYOUTS, move || {
+                MCTS::new(escape_policy)
+                    .with_reward(reward)
+                    .with_evaluator(HeuristicEvaluator::new(reward))
+            })))
+        }
+        #[cfg(feature = "mlp")]
+        "mlp" => {
+            let path = arg.ok_or("mlp needs a path, like mlp:policy.mlp")?;
+            // The policy's own value head is the objective, so there's nothing to choose
+            if let Some((_, objective)) = path.rsplit_once(':') {
+                if Reward::from_name(objective).is_some() {
+                    return Err(format!("mlp takes no objective: {}", name));
+                }
+            }
+            let mlp = Rc::new(Mlp::load(path).map_err(|e| format!("{}: {}", path, e))?);
+            Ok(Box::new(MctsAgent::new(DEFAULT_PLAYOUTS, move || {
+                MCTS::new(mlp.clone()).with_evaluator(mlp.clone())
+            })))
+        }
+        _ => Err(format!("unknown agent: {}", name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::card::{Card, PANDA_DECK};
+
+    #[test]
+    fn names() {
+        for name in [
+            "escape",
+            "random",
+            "mcts",
+            "heuristic:win-by-6",
+            "solver-escape",
+        ] {
+            assert!(agent_from_name(name).is_ok(), "{}", name);
+        }
+        assert!(agent_from_name("mcts:nonsense").is_err());
+        assert!(agent_from_name("solver-nobody").is_err());
+        #[cfg(feature = "mlp")]
+        assert!(agent_from_name("mlp:policy.mlp:win-by-6").is_err());
+    }
+
+    #[test]
+    fn solver_first_plays_out_the_win() {
+        let mut game = Game::new();
+        game.turn = 4;
+        game.mana = 4;
+        game.add_cards_to_hand(
+            vec![
+                Card::Foxy,
+                Card::Shadowstep,
+                Card::Scabbs,
+                Card::Shark,
+                Card::Tenwu,
+                Card::Pillager,
+                Card::Pillager,
+            ]
+            .into_iter(),
+        );
+        let mut agent = agent_from_name("solver-random").unwrap();
+        agent.reset();
+        while game.life > 0 {
+            let action = agent.act(&game);
+            assert_ne!(action, Action::EndTurn);
+            game.take_action(&action);
+            agent.observe(&action, &game);
+        }
+    }
+
+    #[test]
+    fn agents_finish_games() {
+        for name in ["escape", "random", "solver-escape"] {
+            let mut agent = agent_from_name(name).unwrap();
+            let mut game = Game::new_going_first(PANDA_DECK);
+            agent.reset();
+            while game.turn < 4 {
+                let action = agent.act(&game);
+                assert!(game.actions().contains(&action));
+                game.take_action(&action);
+                agent.observe(&action, &game);
+            }
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use goldfish::cli::Args;
+use goldfish::mlp::WinMlp;
+use goldfish::selfplay::{read_shards, WIN_TURNS};
+use goldfish::stats::{brier_score, calibration};
+
+const USAGE: &str = "\
+usage: calibrate [options]
+
+Compares a win estimator's predictions against what actually happened in self-play
+games it wasn't trained on.
+
+options:
+  --data <dir>          held-out shards to check against (default heldout)
+  --win <path>          the exported estimator (default win.mlp)
+  --turn <n>            only check P(kill by turn n), rather than every turn
+  --bins <n>            how many probability bins to report (default 10)
+  --help                show this message
+";
+
+fn main() {
+    let args = Args::from_env_with_usage(USAGE);
+    let data = args.get_str("data").unwrap_or("heldout");
+    let win = WinMlp::load(args.get_str("win").unwrap_or("win.mlp")).unwrap();
+    let num_bins: usize = args.get_or("bins", 10);
+    let turns: Vec<usize> = match args.get::<usize>("turn") {
+        Some(turn) => {
+            assert!(
+                (1..=WIN_TURNS).contains(&turn),
+                "--turn must be 1 to {}",
+                WIN_TURNS
+            );
+            vec![turn]
+        }
+        None => (1..=WIN_TURNS).collect(),
+    };
+
+    let samples = read_shards(data).unwrap();
+    let mut predictions = Vec::new();
+    for sample in &samples {
+        let chances = win.forward(&sample.features);
+        let targets = sample.win_targets();
+        for turn in &turns {
+            predictions.push((chances[turn - 1] as f64, targets[turn - 1] > 0.5));
+        }
+    }
+    println!(
+        "{} predictions from {} samples in {}",
+        predictions.len(),
+        samples.len(),
+        data
+    );
+
+    println!(
+        "{:>11} {:>8} {:>10} {:>8}",
+        "bin", "count", "predicted", "actual"
+    );
+    for bin in calibration(&predictions, num_bins) {
+        println!(
+            "{:>4.0}%-{:>3.0}% {:>8} {:>9.1}% {:>7.1}%",
+            100.0 * bin.lower,
+            100.0 * bin.upper,
+            bin.count,
+            100.0 * bin.predicted,
+            100.0 * bin.actual
+        );
+    }
+    println!("brier score: {:.4}", brier_score(&predictions));
+}
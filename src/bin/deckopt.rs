@@ -0,0 +1,143 @@
+use std::thread;
+
+use goldfish::agent::{agent_from_name, AGENT_NAMES};
+use goldfish::card::{check_deck, parse_deck, Card};
+use goldfish::cli::{usage_error, Args};
+use goldfish::compare::run_comparison;
+use goldfish::deckopt::{deck_diff, hill_climb, Evaluated};
+use goldfish::game::SolverLimit;
+use goldfish::rng;
+use goldfish::sim::Limits;
+use goldfish::stats::{paired_difference, Summary};
+
+const USAGE: &str = "\
+usage: deckopt [options]
+
+Hill climbs over one-card swaps, keeping a swap when it makes the deck kill significantly
+faster on the same seeded games. Then replays the best lists on fresh seeds, for
+unbiased confidence intervals.
+
+options:
+  --deck <deck>        where to start: \"panda\" or a comma-separated list of cards
+                       (default panda)
+  --pool <cards>       comma-separated cards that can be swapped in (default every card)
+  --agent <name>       who plays the games (default heuristic). Agents are escape, random,
+                       mcts[:objective], heuristic[:objective], mlp:<path>, or solver-<agent>.
+  --games <n>          seeded games per deck while climbing (default 100)
+  --check-games <n>    fresh seeded games per deck for the final report (default 400)
+  --seed <n>           the first seed, which also picks the candidate swaps (default 0)
+  --candidates <n>     swaps to try at each step (default 20)
+  --steps <n>          the most swaps to make (default 10)
+  --top <n>            how many of the fastest lists to report (default 5)
+  --turns <n>          give up on a game when it reaches this turn (default 10)
+  --nodes <n>          how many positions the solver may search for lethal at the start
+                       of each turn (default 200000), which gives the same results on
+                       any machine and with any number of threads
+  --time <secs>        search for lethal for this long instead of --nodes. The results
+                       then depend on the machine's speed and load, and on --threads.
+  --threads <n>        how many games to play at once (default the number of cores)
+  --help               show this message
+";
+
+fn main() {
+    let args = Args::from_env_with_usage(USAGE);
+    let start = parse_deck(args.get_str("deck").unwrap_or("panda"))
+        .unwrap_or_else(|e| usage_error(&e, USAGE));
+    check_deck(&start).unwrap_or_else(|e| usage_error(&e, USAGE));
+    let pool = match args.get_str("pool") {
+        Some(s) => parse_deck(s).unwrap_or_else(|e| usage_error(&e, USAGE)),
+        None => enum_iterator::all::<Card>()
+            .filter(|c| *c != Card::Unknown)
+            .collect(),
+    };
+    let agent = args.get_str("agent").unwrap_or("heuristic").to_string();
+    if let Err(e) = agent_from_name(&agent) {
+        usage_error(&format!("{}. agents are {}", e, AGENT_NAMES), USAGE);
+    }
+    let num_games: u64 = args.get_or("games", 100);
+    let check_games: u64 = args.get_or("check-games", 400);
+    let first_seed: u64 = args.get_or("seed", 0);
+    let candidates: usize = args.get_or("candidates", 20);
+    let max_steps: usize = args.get_or("steps", 10);
+    let top: usize = args.get_or("top", 5);
+    let defaults = Limits {
+        solver: SolverLimit::Nodes(200_000),
+        ..Limits::default()
+    };
+    let limits = Limits::from_args(&args, defaults).unwrap_or_else(|e| usage_error(&e, USAGE));
+    let threads: usize = args.get_or(
+        "threads",
+        thread::available_parallelism().map_or(1, |n| n.get()),
+    );
+
+    let kill_turns = |deck: &[Card], seeds: &[u64]| -> Vec<f64> {
+        run_comparison(
+            deck,
+            std::slice::from_ref(&agent),
+            seeds,
+            &limits,
+            threads,
+            &|_, _, _| {},
+        )
+        .unwrap()
+        .kill_turns(0)
+    };
+
+    // Every deck sees the same seeds, so that comparisons between them are paired
+    let seeds: Vec<u64> = (first_seed..first_seed + num_games).collect();
+    let mut evaluations = 0;
+    let mut evaluate = |deck: &[Card]| {
+        evaluations += 1;
+        eprint!("\rdecks evaluated: {}", evaluations);
+        kill_turns(deck, &seeds)
+    };
+    let mut report = |e: &Evaluated| {
+        eprintln!();
+        println!("{}: {}", deck_diff(&start, &e.deck), e.summary());
+    };
+    rng::seed(first_seed);
+    let mut evaluated = hill_climb(
+        &start,
+        &pool,
+        candidates,
+        max_steps,
+        &mut evaluate,
+        &mut report,
+    );
+    eprintln!();
+
+    // Picking the fastest lists favors the ones that got lucky on these seeds,
+    // so check them again on seeds that the search never saw
+    evaluated.sort_by(|a, b| a.summary().mean.total_cmp(&b.summary().mean));
+    // Swaps keep the other cards where they were, so that the shuffles line up across decks.
+    // Sorting is just for spotting the same list reached in two ways.
+    let mut best: Vec<Vec<Card>> = Vec::new();
+    let mut seen: Vec<Vec<Card>> = Vec::new();
+    for e in evaluated {
+        let mut sorted = e.deck.clone();
+        sorted.sort_by_key(|c| c.to_string());
+        if best.len() < top && !seen.contains(&sorted) {
+            seen.push(sorted);
+            best.push(e.deck);
+        }
+    }
+    let check_seeds: Vec<u64> =
+        (first_seed + num_games..first_seed + num_games + check_games).collect();
+    let baseline = kill_turns(&start, &check_seeds);
+    println!(
+        "\nstarting deck on {} fresh seeds: {}",
+        check_games,
+        Summary::new(&baseline)
+    );
+    for deck in best {
+        let turns = kill_turns(&deck, &check_seeds);
+        println!("\n{}", deck_diff(&start, &deck));
+        println!("  kill turn {}", Summary::new(&turns));
+        println!(
+            "  versus the start {}",
+            paired_difference(&turns, &baseline)
+        );
+        let names: Vec<String> = deck.iter().map(|c| c.to_string()).collect();
+        println!("  {}", names.join(","));
+    }
+}
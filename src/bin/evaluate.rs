@@ -1,50 +1,122 @@
-use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Instant;
 
-use goldfish::card::PANDA_DECK;
-use goldfish::game::Game;
-use goldfish::mcts;
+use goldfish::agent::AGENT_NAMES;
+use goldfish::card::parse_deck;
+use goldfish::cli::{usage_error, Args};
+use goldfish::compare::run_comparison;
+use goldfish::sim::{GameResult, Limits};
 
-const NUM_GAMES: usize = 100;
+// Plays every agent on the same seeded games, so that the comparison is paired.
+// Games run in parallel, and the results only depend on the seeds, not the thread count.
+const USAGE: &str = "\
+usage: evaluate [options]
 
-fn main() {
-    println!("evaluating...");
-
-    // turn_map maps the turn to the number of games where we won on that turn
-    let mut turn_map = BTreeMap::new();
+Plays each agent on the same seeded games and compares how fast they kill.
 
-    for i in 0..NUM_GAMES {
-        let mut game = Game::new_going_random(PANDA_DECK);
+options:
+  --agent <names>   comma-separated agents to compare (default mcts,heuristic).
+                    Agents are escape, random, mcts[:objective], heuristic[:objective],
+                    mlp:<path>, or solver-<agent>, with objectives like win-by-6.
+  --deck <deck>     \"panda\" or a comma-separated list of cards (default panda)
+  --games <n>       games per agent (default 100)
+  --seed <n>        the first seed (default 0)
+  --turns <n>       give up on a game when it reaches this turn (default 10)
+  --nodes <n>       how many positions the solver may search for lethal at the start of
+                    each turn (default 1000000), which gives the same results on any
+                    machine and with any number of threads
+  --time <secs>     search for lethal for this long instead of --nodes. The results
+                    then depend on the machine's speed and load, and on --threads.
+  --threads <n>     how many games to play at once (default the number of cores)
+  --cards           also report how often each card was drawn, played, part of the
+                    winning line, stranded in hand at the end, or burned by a full hand,
+                    with copies from the deck apart from generated ones like The Coin
+  --quiet           print only the final report
+  --verbose         print each game's result as it finishes
+  --json <path>     also write the results as JSON, with each agent's card totals
+  --csv <path>      also write each game's result as CSV, without card statistics
+  --help            show this message
+";
 
-        loop {
-            let action = mcts::mcts_action(&game);
-            game.take_action(&action);
+fn main() {
+    let args = Args::from_env_with_usage(USAGE);
+    let deck = parse_deck(args.get_str("deck").unwrap_or("panda"))
+        .unwrap_or_else(|e| usage_error(&e, USAGE));
+    let limits =
+        Limits::from_args(&args, Limits::default()).unwrap_or_else(|e| usage_error(&e, USAGE));
+    let names: Vec<String> = args
+        .get_str("agent")
+        .unwrap_or("mcts,heuristic")
+        .split(',')
+        .map(|s| s.to_string())
+        .collect();
+    let num_games: u64 = args.get_or("games", 100);
+    let first_seed: u64 = args.get_or("seed", 0);
+    let threads: usize = args.get_or(
+        "threads",
+        thread::available_parallelism().map_or(1, |n| n.get()),
+    );
+    let quiet = args.has("quiet");
+    let verbose = args.has("verbose");
 
-            if game.turn >= 10 {
-                println!("game {} failed", i);
-                println!("hand: {}", game.hand_string());
-                break;
-            }
+    let seeds: Vec<u64> = (first_seed..first_seed + num_games).collect();
+    let total = names.len() * seeds.len();
+    let done = AtomicUsize::new(0);
+    let start = Instant::now();
+    if !quiet {
+        eprintln!(
+            "playing {} games with {} agents on {} threads",
+            seeds.len(),
+            names.len(),
+            threads
+        );
+    }
 
-            if game.turn_is_fresh() && game.print_deterministic_win(1.0) {
-                println!("game {} won on turn {}", i, game.turn);
-                break;
-            }
+    // Progress goes to stderr, so that stdout is just the results
+    let report = |agent: usize, seed: u64, result: &GameResult| {
+        let done = done.fetch_add(1, Ordering::SeqCst) + 1;
+        if quiet {
+            return;
         }
-        *turn_map.entry(game.turn).or_insert(0) += 1;
-        println!();
-    }
+        let mut stderr = io::stderr().lock();
+        if verbose {
+            let outcome = if result.won {
+                format!("won on turn {}", result.kill_turn)
+            } else {
+                "failed".to_string()
+            };
+            // Pad to cover up the progress line
+            let line = format!("{} seed {} {}", names[agent], seed, outcome);
+            writeln!(stderr, "\r{:<50}", line).unwrap();
+        }
+        let elapsed = start.elapsed().as_secs_f64();
+        let eta = elapsed / done as f64 * (total - done) as f64;
+        write!(
+            stderr,
+            "\r{}/{} games, {:.0}s elapsed, ETA {:.0}s   ",
+            done, total, elapsed, eta
+        )
+        .unwrap();
+        if done == total {
+            writeln!(stderr).unwrap();
+        }
+    };
+    let comparison = match run_comparison(&deck, &names, &seeds, &limits, threads, &report) {
+        Ok(comparison) => comparison,
+        Err(e) => usage_error(&format!("{}. agents are {}", e, AGENT_NAMES), USAGE),
+    };
 
-    println!("results:");
-    let mut sum = 0;
-    // Iterate over turn map, sorted by key
-    for (turn, num_wins) in turn_map {
-        sum += turn * num_wins;
-        println!(
-            "turn {}{}: {} wins",
-            turn,
-            if turn == 10 { "+" } else { "" },
-            num_wins
-        );
+    print!("{}", comparison.table());
+    if args.has("cards") {
+        print!("{}", comparison.card_table());
+    }
+    if let Some(path) = args.get_str("json") {
+        fs::write(path, comparison.to_json()).unwrap();
+    }
+    if let Some(path) = args.get_str("csv") {
+        fs::write(path, comparison.to_csv()).unwrap();
     }
-    println!("average win turn: {}", sum as f64 / NUM_GAMES as f64);
 }
@@ -0,0 +1,33 @@
+use tch::Device;
+
+use goldfish::cli::Args;
+use goldfish::model::Checkpoint;
+
+const USAGE: &str = "\
+usage: export [options]
+
+Exports a checkpoint to a file that the mlp module can run without libtorch.
+
+options:
+  --checkpoints <dir>   where the checkpoints are (default checkpoints)
+  --version <n>         the checkpoint to export (default the best, or else the latest)
+  --out <path>          where to write it (default policy.mlp)
+  --help                show this message
+";
+
+fn main() {
+    let args = Args::from_env_with_usage(USAGE);
+    let dir = args.get_str("checkpoints").unwrap_or("checkpoints");
+    let out = args.get_str("out").unwrap_or("policy.mlp");
+
+    let checkpoint = match args.get::<usize>("version") {
+        Some(version) => Checkpoint::load(dir, version).unwrap(),
+        None => match Checkpoint::best(dir).unwrap() {
+            Some(best) => best,
+            None => Checkpoint::latest(dir).unwrap().expect("no checkpoints"),
+        },
+    };
+    let model = checkpoint.load_model(dir, Device::Cpu).unwrap();
+    model.to_mlp().save(out).unwrap();
+    println!("exported checkpoint {} to {}", checkpoint.version, out);
+}
@@ -0,0 +1,120 @@
+use std::rc::Rc;
+
+use tch::Device;
+
+use goldfish::agent::{agent_from_name, Agent, MctsAgent, AGENT_NAMES};
+use goldfish::card::parse_deck;
+use goldfish::cli::{usage_error, Args};
+use goldfish::mcts::MCTS;
+use goldfish::model::{default_device, Checkpoint};
+use goldfish::sim::{play_seeded_game, Limits};
+use goldfish::stats::{paired_difference, Summary};
+
+const USAGE: &str = "\
+usage: gate [options]
+
+Plays the same seeded games with a new checkpoint and with the incumbent,
+and promotes the new checkpoint only if it kills significantly faster.
+
+options:
+  --checkpoints <dir>   where the checkpoints are (default checkpoints)
+  --new <version>       the checkpoint to test (default the latest)
+  --incumbent <which>   a checkpoint version, \"best\", or an agent name like mcts or
+                        solver-heuristic (default best, or mcts if nothing is best yet)
+  --games <n>           number of seeded games per agent (default 100)
+  --seed <n>            the first seed (default 0)
+  --playouts <n>        MCTS playouts per decision with a checkpoint (default 200)
+  --batch-size <n>      states to evaluate at once with a checkpoint (default 16)
+  --deck <deck>         \"panda\" or a comma-separated list of cards (default panda)
+  --cpu                 run on the CPU even if CUDA is available
+  --dry-run             report the result without promoting
+  --help                show this message
+";
+
+// Loads a checkpoint the options asked for by version
+fn load_checkpoint(dir: &str, version: usize) -> Checkpoint {
+    Checkpoint::load(dir, version)
+        .unwrap_or_else(|e| usage_error(&format!("checkpoint {}: {}", version, e), USAGE))
+}
+
+fn main() {
+    let args = Args::from_env_with_usage(USAGE);
+    let dir = args.get_str("checkpoints").unwrap_or("checkpoints");
+    let num_games: u64 = args.get_or("games", 100);
+    let first_seed: u64 = args.get_or("seed", 0);
+    let playouts: usize = args.get_or("playouts", 200);
+    let batch_size: usize = args.get_or("batch-size", 16);
+    let deck = parse_deck(args.get_str("deck").unwrap_or("panda"))
+        .unwrap_or_else(|e| usage_error(&e, USAGE));
+    let device = if args.has("cpu") {
+        Device::Cpu
+    } else {
+        default_device()
+    };
+
+    let new = match args.get::<usize>("new") {
+        Some(version) => load_checkpoint(dir, version),
+        None => Checkpoint::latest(dir)
+            .unwrap()
+            .unwrap_or_else(|| usage_error(&format!("no checkpoints in {}", dir), USAGE)),
+    };
+    let best = Checkpoint::best(dir).unwrap();
+    let incumbent = match args.get_str("incumbent") {
+        Some("best") => match best {
+            Some(best) => Ok(best),
+            None => usage_error(&format!("no best checkpoint in {}", dir), USAGE),
+        },
+        Some(which) => match which.parse() {
+            Ok(version) => Ok(load_checkpoint(dir, version)),
+            Err(_) => Err(which),
+        },
+        None => best.ok_or("mcts"),
+    };
+
+    let model_agent = |checkpoint: &Checkpoint| -> Box<dyn Agent> {
+        let model = Rc::new(checkpoint.load_model(dir, device).unwrap());
+        Box::new(MctsAgent::new(playouts, move || {
+            MCTS::new(model.clone())
+                .with_evaluator(model.clone())
+                .with_batch_size(batch_size)
+        }))
+    };
+    let (incumbent_name, mut incumbent_agent) = match &incumbent {
+        Ok(c) => (format!("checkpoint {}", c.version), model_agent(c)),
+        Err(name) => match agent_from_name(name) {
+            Ok(agent) => (name.to_string(), agent),
+            Err(e) => usage_error(&format!("{}. agents are {}", e, AGENT_NAMES), USAGE),
+        },
+    };
+    let mut new_agent = model_agent(&new);
+    let mut new_turns = Vec::new();
+    let mut incumbent_turns = Vec::new();
+    let limits = Limits::default();
+    for seed in first_seed..first_seed + num_games {
+        let a = play_seeded_game(&deck, seed, new_agent.as_mut(), &limits);
+        let b = play_seeded_game(&deck, seed, incumbent_agent.as_mut(), &limits);
+        println!(
+            "seed {}: checkpoint {} killed on turn {}, {} on turn {}",
+            seed, new.version, a.kill_turn, incumbent_name, b.kill_turn
+        );
+        new_turns.push(a.kill_turn as f64);
+        incumbent_turns.push(b.kill_turn as f64);
+    }
+
+    println!();
+    println!("checkpoint {}: {}", new.version, Summary::new(&new_turns));
+    println!("{}: {}", incumbent_name, Summary::new(&incumbent_turns));
+    let difference = paired_difference(&new_turns, &incumbent_turns);
+    println!("difference: {}", difference);
+
+    // Lower kill turns are better, so the whole interval should be below zero
+    if difference.confidence_interval().1 < 0.0 {
+        println!("checkpoint {} is significantly better", new.version);
+        if !args.has("dry-run") {
+            new.promote(dir).unwrap();
+            println!("promoted checkpoint {}", new.version);
+        }
+    } else {
+        println!("checkpoint {} is not significantly better", new.version);
+    }
+}
@@ -0,0 +1,53 @@
+use std::fs;
+
+use goldfish::card::parse_deck;
+use goldfish::cli::Args;
+use goldfish::imitation::label_game;
+use goldfish::mcts::Reward;
+use goldfish::rng;
+use goldfish::selfplay::{shard_path, write_shard};
+
+const USAGE: &str = "\
+usage: imitate [options]
+
+Writes shards of positions labeled with the escape bot's move, or the solver's first
+move where there is lethal. Train on these before self-play to get a better prior,
+by giving train the --out directory as its data.
+
+options:
+  --deck <deck>         \"panda\" or a comma-separated list of cards (default panda)
+  --games <n>           number of games to label (default 1000)
+  --shard-games <n>     games per shard (default 100)
+  --explore <p>         chance of a random move instead of the escape bot's (default 0.2)
+  --solver-time <secs>  time limit for finding lethal in each position (default 0.1)
+  --seed <n>            seed for reproducible data
+  --out <dir>           where to write the shards (default imitation)
+  --help                show this message
+";
+
+fn main() {
+    let args = Args::from_env_with_usage(USAGE);
+    let deck = parse_deck(args.get_str("deck").unwrap_or("panda")).unwrap();
+    let num_games: usize = args.get_or("games", 1000);
+    let shard_games: usize = args.get_or("shard-games", 100);
+    let explore: f64 = args.get_or("explore", 0.2);
+    let solver_time: f64 = args.get_or("solver-time", 0.1);
+    let out = args.get_str("out").unwrap_or("imitation");
+    if let Some(seed) = args.get("seed") {
+        rng::seed(seed);
+    }
+
+    fs::create_dir_all(out).unwrap();
+    let mut samples = Vec::new();
+    let mut shard = 0;
+    for i in 0..num_games {
+        samples.extend(label_game(&deck, explore, solver_time, Reward::MeanTurn));
+        if (i + 1) % shard_games == 0 || i + 1 == num_games {
+            let path = shard_path(out, shard);
+            write_shard(&path, &samples).unwrap();
+            println!("wrote {} samples to {}", samples.len(), path.display());
+            samples.clear();
+            shard += 1;
+        }
+    }
+}
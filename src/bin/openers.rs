@@ -0,0 +1,122 @@
+use std::fs;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use goldfish::agent::AGENT_NAMES;
+use goldfish::card::{check_deck, parse_deck};
+use goldfish::cli::{usage_error, Args};
+use goldfish::compare::run_games;
+use goldfish::openers::{cards_string, parse_cards, Opener, OpenerTable};
+use goldfish::sim::{starting_game, Limits};
+
+const USAGE: &str = "\
+usage: openers [options]
+
+Samples many seeded openers, going first and second, plays several games from each with
+different draws, and tables how fast each opening hand kills. The opener is the hand we
+keep before the first turn, without The Coin or the first draw. Games from the same
+opener aren't independent, so the intervals are somewhat too narrow.
+
+options:
+  --agent <name>     who plays the games (default mcts). Agents are escape, random,
+                     mcts[:objective], heuristic[:objective], mlp:<path>, or solver-<agent>.
+  --deck <deck>      \"panda\" or a comma-separated list of 30 cards that follows the
+                     deckbuilding rules (default panda)
+  --openers <n>      how many openers to sample (default 100)
+  --continuations <n>
+                     how many games to play from each opener, with different draws
+                     after the first turn (default 10)
+  --seed <n>         the first seed (default 0)
+  --turns <n>        give up on a game when it reaches this turn (default 10)
+  --nodes <n>        how many positions the solver may search for lethal at the start of
+                     each turn (default 1000000), which gives the same results on any
+                     machine and with any number of threads
+  --time <secs>      search for lethal for this long instead of --nodes. The results
+                     then depend on the machine's speed and load, and on --threads.
+  --threads <n>      how many games to play at once (default the number of cores)
+  --top <n>          how many of the most common openers to show (default 20)
+  --by-turn <n>      the turn to report kill chances by (default 5)
+  --query <cards>    report the chance to kill by --by-turn when the opener has these cards,
+                     like \"Foxy Fraud + Scabbs Cutterbutter\". Separate queries with \";\".
+  --csv <path>       write each opener's kill turn distribution as CSV
+  --help             show this message
+";
+
+fn main() {
+    let args = Args::from_env_with_usage(USAGE);
+    let deck = parse_deck(args.get_str("deck").unwrap_or("panda"))
+        .unwrap_or_else(|e| usage_error(&e, USAGE));
+    check_deck(&deck).unwrap_or_else(|e| usage_error(&e, USAGE));
+    let agent = args.get_str("agent").unwrap_or("mcts").to_string();
+    let num_openers: u64 = args.get_or("openers", 100);
+    let continuations: u64 = args.get_or("continuations", 10);
+    let first_seed: u64 = args.get_or("seed", 0);
+    let limits =
+        Limits::from_args(&args, Limits::default()).unwrap_or_else(|e| usage_error(&e, USAGE));
+    let threads: usize = args.get_or(
+        "threads",
+        thread::available_parallelism().map_or(1, |n| n.get()),
+    );
+    let top: usize = args.get_or("top", 20);
+    let by_turn: i32 = args.get_or("by-turn", 5);
+    let queries: Vec<Vec<_>> = args
+        .get_str("query")
+        .unwrap_or("")
+        .split(';')
+        .filter(|q| !q.trim().is_empty())
+        .map(|q| parse_cards(q).unwrap_or_else(|e| usage_error(&e, USAGE)))
+        .collect();
+
+    let games: Vec<(u64, u64)> = (first_seed..first_seed + num_openers)
+        .flat_map(|seed| (0..continuations).map(move |c| (seed, c)))
+        .collect();
+    let done = AtomicUsize::new(0);
+    let report = |_: usize, _: u64, _: &_| {
+        let done = done.fetch_add(1, Ordering::SeqCst) + 1;
+        eprint!("\r{}/{} games", done, games.len());
+    };
+    let results = match run_games(
+        &deck,
+        std::slice::from_ref(&agent),
+        &games,
+        &limits,
+        threads,
+        &report,
+    ) {
+        Ok(results) => results,
+        Err(e) => usage_error(&format!("{}. agents are {}", e, AGENT_NAMES), USAGE),
+    };
+    eprintln!();
+
+    let table = OpenerTable {
+        games: games
+            .iter()
+            .map(|(seed, _)| {
+                let (game, going_first) = starting_game(&deck, *seed);
+                Opener::of(&game, going_first)
+            })
+            .zip(results[0].iter().copied())
+            .collect(),
+        max_turns: limits.max_turns,
+    };
+    print!("{}", table.table(top, by_turn));
+
+    for cards in &queries {
+        println!("\nP(kill by turn {} | {}):", by_turn, cards_string(cards));
+        for (going, going_first) in [
+            ("first", Some(true)),
+            ("second", Some(false)),
+            ("either", None),
+        ] {
+            println!(
+                "  going {:<6} {}",
+                going,
+                table.kill_chance(cards, going_first, by_turn)
+            );
+        }
+    }
+
+    if let Some(path) = args.get_str("csv") {
+        fs::write(path, table.to_csv()).unwrap();
+    }
+}
@@ -1,34 +1,267 @@
-use goldfish::card::PANDA_DECK;
-use goldfish::game::Game;
-use goldfish::mcts::mcts_action;
-use rand::Rng;
+use std::cmp::Reverse;
+use std::io::{self, BufRead, Write};
 
-fn main() {
-    let mut game = if rand::thread_rng().gen_bool(0.5) {
+use goldfish::agent::{agent_from_name, AGENT_NAMES, DEFAULT_PLAYOUTS};
+use goldfish::card::{parse_deck, Card};
+use goldfish::cli::{usage_error, Args};
+use goldfish::game::{Action, Game};
+use goldfish::mcts::{escape_policy, Eviction, MCTS};
+#[cfg(feature = "mlp")]
+use goldfish::mlp::WinMlp;
+use goldfish::rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+// How MCTS with statistics searches and keeps its tree
+struct SearchOptions {
+    playouts: usize,
+    max_nodes: Option<usize>,
+    eviction: Eviction,
+    hashed_keys: bool,
+}
+
+impl SearchOptions {
+    fn from_args(args: &Args) -> SearchOptions {
+        let eviction = match args.get_str("eviction").unwrap_or("least-visited") {
+            "least-visited" => Eviction::LeastVisited,
+            "oldest" => Eviction::Oldest,
+            other => usage_error(&format!("unknown eviction: {}", other), USAGE),
+        };
+        SearchOptions {
+            playouts: args.get_or("playouts", DEFAULT_PLAYOUTS),
+            max_nodes: args.get("max-nodes"),
+            eviction,
+            hashed_keys: args.has("hashed-keys"),
+        }
+    }
+
+    fn new_mcts(&self) -> MCTS {
+        let mut mcts = MCTS::new(escape_policy);
+        if let Some(max_nodes) = self.max_nodes {
+            mcts = mcts.with_max_nodes(max_nodes, self.eviction);
+        }
+        if self.hashed_keys {
+            mcts = mcts.with_hashed_keys();
+        }
+        mcts
+    }
+}
+
+// Searches with MCTS, printing the statistics for each action and the size of the tree
+fn mcts_with_stats(game: &Game, options: &SearchOptions) -> Action {
+    let mut mcts = options.new_mcts();
+    mcts.search(game, options.playouts);
+    let mut stats = mcts.root_stats(game);
+    stats.sort_by_key(|s| Reverse(s.visits));
+    for s in &stats {
+        println!(
+            "  {:>4} visits, Q = {:.2}, P = {:.2}: {}",
+            s.visits,
+            s.reward,
+            s.prior,
+            game.action_string(&s.action)
+        );
+    }
+    println!(
+        "  {} states, about {} KB",
+        mcts.node_count(),
+        mcts.approximate_bytes() / 1024
+    );
+    mcts.best_action(game)
+}
+
+fn new_game(deck: &[Card]) -> Game {
+    if rng::with(|rng| rng.gen_bool(0.5)) {
         println!("going first.");
-        Game::new_going_first(PANDA_DECK)
+        Game::new_going_first(deck)
     } else {
         println!("going second.");
-        Game::new_going_second(PANDA_DECK)
+        Game::new_going_second(deck)
+    }
+}
+
+const COMMANDS: &str = "\
+commands:
+  <number>             take the numbered action
+  <card> [-> <card>]   play or pick a card by name, like Shadowstep -> Spectral Pillager
+  state                show the game and the numbered actions
+  lethal               ask the solver for lethal
+  hint                 ask MCTS for its move, with its statistics
+  undo                 take back the last action
+  restart              start a new game
+  help                 show this message
+  quit                 stop playing
+";
+
+fn print_state(game: &Game) {
+    println!("\nturn {}", game.turn);
+    print!("{}", game);
+    if game.life <= 0 {
+        println!("you won on turn {}! undo, restart, or quit", game.turn);
+        return;
+    }
+    for (i, action) in game.actions().iter().enumerate() {
+        println!("  {}: {}", i + 1, game.action_string(action));
+    }
+}
+
+// Reads an action as its number in the list, or as card names
+fn parse_action(game: &Game, input: &str) -> Option<Action> {
+    if let Ok(n) = input.parse::<usize>() {
+        return game.actions().get(n.wrapping_sub(1)).copied();
+    }
+    let mut names = input.split("->");
+    let card = Card::parse(names.next()?)?;
+    let target = match names.next() {
+        Some(name) => Some(Card::parse(name)?),
+        None => None,
     };
+    game.action_for_card(card, target)
+}
+
+// A human chooses the actions, with the solver and MCTS on hand for hints.
+// The game draws from its own stream of randomness, which undo rewinds along with the game,
+// so that hints don't change the draws and taking the same action again draws the same cards.
+fn play_human(deck: &[Card], options: &SearchOptions, time_limit: f64) {
+    let mut game_rng = rng::with(|rng| StdRng::seed_from_u64(rng.gen()));
+    let mut game = rng::scoped(&mut game_rng, || new_game(deck));
+    let mut history: Vec<(Game, StdRng)> = Vec::new();
+    print!("{}", COMMANDS);
+    print_state(&game);
+    let mut lines = io::stdin().lock().lines();
+    loop {
+        print!("> ");
+        io::stdout().flush().unwrap();
+        let Some(line) = lines.next() else {
+            break;
+        };
+        let line = line.unwrap();
+        match line.trim() {
+            "" => {}
+            "help" => print!("{}", COMMANDS),
+            "state" => print_state(&game),
+            "quit" => break,
+            "lethal" => {
+                game.print_deterministic_win(time_limit);
+            }
+            "hint" if game.life > 0 => {
+                let action = mcts_with_stats(&game, options);
+                println!("MCTS suggests: {}", game.action_string(&action));
+            }
+            "undo" => match history.pop() {
+                Some((previous, previous_rng)) => {
+                    game = previous;
+                    game_rng = previous_rng;
+                    print_state(&game);
+                }
+                None => println!("nothing to undo"),
+            },
+            "restart" => {
+                game = rng::scoped(&mut game_rng, || new_game(deck));
+                history.clear();
+                print_state(&game);
+            }
+            input => match parse_action(&game, input).filter(|_| game.life > 0) {
+                Some(action) => {
+                    println!("action: {}", game.action_string(&action));
+                    history.push((game.clone(), game_rng.clone()));
+                    rng::scoped(&mut game_rng, || game.take_action(&action));
+                    print_state(&game);
+                }
+                None => println!("not a legal action: {}. type help for commands", input),
+            },
+        }
+    }
+}
+
+const USAGE: &str = "\
+usage: play [options]
+
+Plays a single game against an empty board, printing each decision.
+
+options:
+  --deck <deck>     \"panda\" or a comma-separated list of cards (default panda)
+  --seed <n>        seed the shuffle and the agent, to replay a game (default random)
+  --agent <name>    who plays, instead of MCTS with its statistics shown.
+                    Agents are escape, random, mcts[:objective], heuristic[:objective],
+                    mlp:<path>, or solver-<agent>.
+  --playouts <n>    playouts per decision for MCTS with statistics (default 200)
+  --max-nodes <n>   cap the states MCTS with statistics keeps (default no cap)
+  --eviction <how>  which states to drop at the cap, least-visited or oldest
+                    (default least-visited)
+  --hashed-keys     key states by hash instead of a full copy, to save memory
+  --time <secs>     time to look for lethal at the start of each turn (default 5.0)
+  --turns <n>       give up when the game reaches this turn (default 10)
+  --win <path>      an exported win estimator, from winprob, to report our chances with
+  --human           choose the actions yourself, with hints on request.
+                    Takes --deck, --seed, --time, and the MCTS options.
+  --help            show this message
+";
+
+fn main() {
+    let args = Args::from_env_with_usage(USAGE);
+    let deck = parse_deck(args.get_str("deck").unwrap_or("panda"))
+        .unwrap_or_else(|e| usage_error(&e, USAGE));
+    let search = SearchOptions::from_args(&args);
+    let time_limit: f64 = args.get_or("time", 5.0);
+    let max_turns: i32 = args.get_or("turns", 10);
+    if let Some(seed) = args.get::<u64>("seed") {
+        rng::seed(seed);
+    }
+    if args.has("human") {
+        if ["agent", "turns", "win"].iter().any(|name| args.has(name)) {
+            usage_error("--human doesn't take --agent, --turns, or --win", USAGE);
+        }
+        play_human(&deck, &search, time_limit);
+        return;
+    }
+    let mut agent = args
+        .get_str("agent")
+        .map(|name| match agent_from_name(name) {
+            Ok(agent) => agent,
+            Err(e) => usage_error(&format!("{}. agents are {}", e, AGENT_NAMES), USAGE),
+        });
+    #[cfg(feature = "mlp")]
+    let win = args.get_str("win").map(|path| {
+        WinMlp::load(path).unwrap_or_else(|e| usage_error(&format!("{}: {}", path, e), USAGE))
+    });
+    #[cfg(not(feature = "mlp"))]
+    if args.has("win") {
+        usage_error("--win needs the mlp feature", USAGE);
+    }
+
+    let mut game = new_game(&deck);
 
     loop {
         if game.turn_is_fresh() {
             println!("\nturn {}", game.turn);
             println!("{}", game);
-            if game.print_deterministic_win(5.0) {
+            if game.print_deterministic_win(time_limit) {
                 break;
             }
+            #[cfg(feature = "mlp")]
+            if let Some(win) = &win {
+                println!(
+                    "estimated P(kill by turn N): {}",
+                    win.kill_chances_string(&game)
+                );
+            }
         }
 
-        let action = mcts_action(&game);
+        let action = match &mut agent {
+            Some(agent) => agent.act(&game),
+            None => mcts_with_stats(&game, &search),
+        };
         println!("\naction: {}", game.action_string(&action));
         game.take_action(&action);
+        if let Some(agent) = &mut agent {
+            agent.observe(&action, &game);
+        }
 
         println!("hand: {}", game.hand_string());
         println!("mana: {}", game.mana);
 
-        if game.turn >= 10 {
+        if game.turn >= max_turns {
             println!("we give up");
             break;
         }
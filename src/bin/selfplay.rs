@@ -0,0 +1,60 @@
+use std::fs;
+
+use goldfish::card::parse_deck;
+use goldfish::cli::Args;
+use goldfish::mcts::{escape_policy, Reward, MCTS};
+use goldfish::rng;
+use goldfish::selfplay::{play_game, shard_path, write_shard};
+
+const USAGE: &str = "\
+usage: selfplay [options]
+
+Plays MCTS games and writes the decisions to shards of training data.
+The same seed and options always write the same shards.
+
+options:
+  --deck <deck>          \"panda\" or a comma-separated list of cards (default panda)
+  --games <n>            number of games to play (default 100)
+  --shard-games <n>      games per shard (default 10)
+  --playouts <n>         MCTS playouts per decision (default 200)
+  --reward <objective>   like \"mean-turn\" or \"win-by-6\" (default mean-turn)
+  --seed <n>             seed for the games and the searches (default 0)
+  --out <dir>            where to write the shards (default selfplay)
+  --help                 show this message
+";
+
+fn main() {
+    let args = Args::from_env_with_usage(USAGE);
+    let deck = parse_deck(args.get_str("deck").unwrap_or("panda")).unwrap();
+    let num_games: usize = args.get_or("games", 100);
+    let shard_games: usize = args.get_or("shard-games", 10);
+    let playouts: usize = args.get_or("playouts", 200);
+    let reward_name = args.get_str("reward").unwrap_or("mean-turn");
+    let reward = Reward::from_name(reward_name).expect("unknown reward");
+    let out = args.get_str("out").unwrap_or("selfplay");
+    rng::seed(args.get_or("seed", 0));
+
+    fs::create_dir_all(out).unwrap();
+    let mut samples = Vec::new();
+    let mut shard = 0;
+    for i in 0..num_games {
+        let game_samples = play_game(&deck, playouts, reward, &mut || {
+            MCTS::new(escape_policy).with_reward(reward)
+        });
+        println!(
+            "game {}: {} decisions, killed on turn {}",
+            i,
+            game_samples.len(),
+            game_samples[0].kill_turn
+        );
+        samples.extend(game_samples);
+
+        if (i + 1) % shard_games == 0 || i + 1 == num_games {
+            let path = shard_path(out, shard);
+            write_shard(&path, &samples).unwrap();
+            println!("wrote {} samples to {}", samples.len(), path.display());
+            samples.clear();
+            shard += 1;
+        }
+    }
+}
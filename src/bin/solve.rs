@@ -0,0 +1,146 @@
+use std::fs;
+
+use goldfish::cli::{usage_error, Args};
+use goldfish::game::{Game, LethalLifeError, Plan, Play, SolverLimit};
+use goldfish::position::parse_position;
+
+const USAGE: &str = "\
+usage: solve [--file <path>] [options]
+
+Looks for lethal this turn from a position, printing the line if there is one,
+and otherwise the most damage we can deal.
+
+options:
+  --file <path>         read the position from a file of \"key: value\" lines, in the
+                        format that games print in, like \"hand: Foxy Fraud, Shadowstep\"
+  --hand <cards>        comma-separated cards in hand. Suffixes like (-1), (potion),
+                        and (tenwu) mark cost reductions and where a card came from.
+  --board <cards>       comma-separated minions on our board
+  --deck <cards>        comma-separated cards left in the deck, or \"panda\"
+  --mana <n>            our mana (default 0)
+  --life <n>            the opponent's life, counting armor (default 30)
+  --storm <n>           cards played this turn already (default 0)
+  --foxy <n>            Foxy Fraud effects waiting for a combo card (default 0)
+  --scabbs <n>          Scabbs Cutterbutter effects on the next card (default 0)
+  --next-scabbs <n>     Scabbs Cutterbutter effects after that (default 0)
+  --prep                a Preparation effect is waiting for a spell
+  --max-life            find the most life we could kill through, ignoring --life
+  --table               with --max-life, show the most life for each mana from 0 to 10.
+                        The 11 searches share the --time or --nodes between them.
+  --time <secs>         how long to search (default 10.0)
+  --nodes <n>           with --max-life, search at most this many positions instead of
+                        for --time, which gives the same answer on any machine
+  --help                show this message
+
+Options override the same keys in the file.
+";
+
+// Prints each play along with the mana and life left after it
+fn print_line(game: &Game, plays: &[Play]) {
+    let mut game = game.clone();
+    for play in plays {
+        let s = game.play_string(play);
+        game.play(play);
+        println!("  {:<48} mana {}, life {}", s, game.mana, game.life);
+    }
+}
+
+fn lethal_life_error_string(e: LethalLifeError) -> String {
+    match e {
+        LethalLifeError::Timeout => "timeout".to_string(),
+        LethalLifeError::WitnessFailed { damage } => {
+            format!(
+                "the line that deals {} damage doesn't kill at that life",
+                damage
+            )
+        }
+    }
+}
+
+fn print_max_life(game: &Game, limit: SolverLimit, table: bool) {
+    if table {
+        println!("\nmana  max life");
+        // The searches for mana 0 through 10 split the limit
+        let limit = match limit {
+            SolverLimit::Seconds(seconds) => SolverLimit::Seconds(seconds / 11.0),
+            SolverLimit::Nodes(nodes) => SolverLimit::Nodes(nodes / 11),
+        };
+        for mana in 0..=10 {
+            let mut game = game.clone();
+            game.mana = mana;
+            match game.max_lethal_life(limit) {
+                Ok((life, _)) => println!("{:>4}  {:>8}", mana, life),
+                Err(e) => println!("{:>4}  {}", mana, lethal_life_error_string(e)),
+            }
+        }
+        return;
+    }
+    match game.max_lethal_life(limit) {
+        Ok((life, plays)) => {
+            println!("\nkills through {} life:", life);
+            let mut game = game.clone();
+            game.life = life;
+            print_line(&game, &plays);
+        }
+        Err(LethalLifeError::Timeout) => println!("\ntimed out"),
+        Err(e) => println!("\n{}", lethal_life_error_string(e)),
+    }
+}
+
+fn main() {
+    let args = Args::from_env_with_usage(USAGE);
+    let mut text = match args.get_str("file") {
+        Some(path) => match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) => usage_error(&format!("can't read {}: {}", path, e), USAGE),
+        },
+        None => String::new(),
+    };
+    for (option, key) in [
+        ("hand", "hand"),
+        ("board", "board"),
+        ("deck", "deck"),
+        ("mana", "mana"),
+        ("life", "life"),
+        ("storm", "storm"),
+        ("foxy", "foxy"),
+        ("scabbs", "scabbs"),
+        ("next-scabbs", "next_scabbs"),
+    ] {
+        if let Some(value) = args.get_str(option) {
+            text.push_str(&format!("\n{}: {}", key, value));
+        }
+    }
+    if args.has("prep") {
+        text.push_str("\nprep_pending: true");
+    }
+    let game = match parse_position(&text) {
+        Ok(game) => game,
+        Err(e) => usage_error(&e, USAGE),
+    };
+    let time_limit: f64 = args.get_or("time", 10.0);
+
+    print!("{}", game);
+    if args.has("max-life") {
+        let limit = match args.get("nodes") {
+            Some(nodes) => SolverLimit::Nodes(nodes),
+            None => SolverLimit::Seconds(time_limit),
+        };
+        print_max_life(&game, limit, args.has("table"));
+        return;
+    }
+    match game.find_deterministic_win(time_limit) {
+        Plan::Win(plays) => {
+            println!("\nlethal:");
+            print_line(&game, &plays);
+        }
+        Plan::Lose => match game.find_max_damage(time_limit) {
+            Some((damage, plays)) => {
+                println!("\nno lethal. at most {} damage:", damage);
+                print_line(&game, &plays);
+            }
+            None => println!("\nno lethal. timed out looking for the most damage"),
+        },
+        Plan::Timeout => println!("\ntimed out looking for lethal"),
+    }
+}
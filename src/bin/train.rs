@@ -0,0 +1,114 @@
+use tch::Device;
+
+use goldfish::cli::Args;
+use goldfish::encoding::ENCODER_VERSION;
+use goldfish::model::{default_device, Checkpoint, Model, TrainConfig};
+use goldfish::selfplay::read_shards;
+
+const USAGE: &str = "\
+usage: train [options]
+
+Trains the policy and value heads on self-play shards, saving a checkpoint after each
+epoch.
+
+options:
+  --data <dir>          where to read shards from (default selfplay)
+  --out <dir>           where to write checkpoints (default checkpoints)
+  --epochs <n>          how many epochs to train (default 10)
+  --lr <x>              learning rate (default 0.001)
+  --batch-size <n>      samples per batch (default 256)
+  --value-weight <x>    weight of the value loss (default 1.0)
+  --deck <deck>         the deck the data came from, for the metadata (default panda)
+  --resume              continue from the latest checkpoint in the output directory,
+                        with its optimizer state. Its settings are the defaults for
+                        --lr, --batch-size, --value-weight, and --deck.
+  --cpu                 run on the CPU even if CUDA is available
+  --help                show this message
+";
+
+fn main() {
+    let args = Args::from_env_with_usage(USAGE);
+    let data = args.get_str("data").unwrap_or("selfplay");
+    let out = args.get_str("out").unwrap_or("checkpoints");
+    let epochs: usize = args.get_or("epochs", 10);
+    let device = if args.has("cpu") {
+        Device::Cpu
+    } else {
+        default_device()
+    };
+
+    // On a resume, the checkpoint's settings are the defaults, and flags override them
+    let resumed = match Checkpoint::latest(out).unwrap() {
+        Some(checkpoint) if args.has("resume") => Ok(checkpoint),
+        latest => Err(latest),
+    };
+    let (base_config, base_deck) = match &resumed {
+        Ok(checkpoint) => (checkpoint.config.clone(), checkpoint.deck.as_str()),
+        Err(_) => (TrainConfig::default(), "panda"),
+    };
+    let config = TrainConfig {
+        learning_rate: args.get_or("lr", base_config.learning_rate),
+        batch_size: args.get_or("batch-size", base_config.batch_size),
+        value_weight: args.get_or("value-weight", base_config.value_weight),
+    };
+    let deck = args.get_str("deck").unwrap_or(base_deck).to_string();
+
+    let (model, mut checkpoint, mut optimizer) = match resumed {
+        Ok(mut checkpoint) => {
+            println!("resuming from checkpoint {}", checkpoint.version);
+            if config != checkpoint.config {
+                println!("  overriding its {:?} with {:?}", checkpoint.config, config);
+            }
+            if deck != checkpoint.deck {
+                println!("  overriding its deck {} with {}", checkpoint.deck, deck);
+            }
+            let model = checkpoint.load_model(out, device).unwrap();
+            let mut optimizer = model.optimizer(&config);
+            let optimizer_path = checkpoint.optimizer_path(out);
+            if optimizer_path.exists() {
+                optimizer.load(&optimizer_path).unwrap();
+            } else {
+                println!(
+                    "  no optimizer state at {}, so Adam starts over",
+                    optimizer_path.display()
+                );
+            }
+            checkpoint.config = config;
+            checkpoint.deck = deck;
+            (model, checkpoint, optimizer)
+        }
+        Err(latest) => {
+            // Fresh runs keep numbering after any existing checkpoints rather than clobbering them
+            let checkpoint = Checkpoint {
+                version: latest.map_or(0, |c| c.version),
+                encoder_version: ENCODER_VERSION,
+                deck,
+                epochs: 0,
+                config,
+            };
+            let model = Model::new(device);
+            let optimizer = model.optimizer(&checkpoint.config);
+            (model, checkpoint, optimizer)
+        }
+    };
+
+    let samples = read_shards(data).unwrap();
+    println!(
+        "training on {} samples from {} on {:?}",
+        samples.len(),
+        data,
+        device
+    );
+
+    for _ in 0..epochs {
+        let (policy_loss, value_loss) =
+            model.train_epoch(&mut optimizer, &samples, &checkpoint.config);
+        checkpoint.version += 1;
+        checkpoint.epochs += 1;
+        checkpoint.save(out, &model, &optimizer).unwrap();
+        println!(
+            "epoch {}: policy loss {:.4}, value loss {:.4}, saved checkpoint {}",
+            checkpoint.epochs, policy_loss, value_loss, checkpoint.version
+        );
+    }
+}
@@ -1,14 +1,48 @@
 #![allow(dead_code)]
 
-use goldfish::card::{Card, CardInstance, UNKNOWN_COST};
+use goldfish::card::{parse_deck, Card, CardInstance, UNKNOWN_COST};
+use goldfish::cli::{usage_error, Args};
 use goldfish::game::Game;
+#[cfg(feature = "mlp")]
+use goldfish::mlp::{Mlp, WinMlp};
 use regex::Regex;
 use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
 use std::thread;
 use std::time;
 
-const FILENAME: &str = r"C:\Program Files (x86)\Hearthstone\Logs\Power.log";
+const DEFAULT_LOG: &str = r"C:\Program Files (x86)\Hearthstone\Logs\Power.log";
+
+const USAGE: &str = "\
+usage: watch --battletag <name#1234> [options]
+
+Follows the Hearthstone log and prints lethal whenever we have it.
+
+options:
+  --battletag <tag>   our battletag, like lacker#1660, to tell our lines from the opponent's
+  --log <path>        the Power.log to follow
+                      (default C:\\Program Files (x86)\\Hearthstone\\Logs\\Power.log)
+  --deck <deck>       \"panda\" or a comma-separated list of the cards we started with
+                      (default panda). The cards we've seen in the log are taken out.
+  --time <secs>       time to look for lethal each time our options change (default 20.0)
+  --mlp <path>        an exported policy to suggest moves with when there is no lethal
+  --win <path>        an exported win estimator, from winprob, to report our chances with
+                      when there is no lethal
+  --help              show this message
+";
+
+// Where the log is and who we are in it
+struct LogConfig {
+    path: String,
+    battletag: String,
+}
+
+impl LogConfig {
+    // The name shown in the log without the #1234 part
+    fn name(&self) -> &str {
+        self.battletag.split('#').next().unwrap()
+    }
+}
 
 struct LogData {
     num_lines: usize,
@@ -19,10 +53,12 @@ struct LogData {
     opponent_armor: i32,
     last_create_game_line: usize,
     renathal: bool,
+    turn: i32,                 // our turn number, counting only our own turns
+    seen: BTreeMap<i32, Card>, // our cards that have been revealed, by entity id
 }
 
-fn read_log(last_create_game_line: usize) -> Result<LogData, std::io::Error> {
-    let file_data = fs::read_to_string(FILENAME)?;
+fn read_log(config: &LogConfig, last_create_game_line: usize) -> Result<LogData, std::io::Error> {
+    let file_data = fs::read_to_string(&config.path)?;
     let lines: Vec<_> = file_data.lines().collect();
     let mut log_data: LogData = LogData {
         num_lines: lines.len(),
@@ -33,6 +69,8 @@ fn read_log(last_create_game_line: usize) -> Result<LogData, std::io::Error> {
         opponent_armor: 0,
         last_create_game_line,
         renathal: false,
+        turn: 0,
+        seen: BTreeMap::new(),
     };
 
     // Populate the id -> card_id map
@@ -46,15 +84,22 @@ fn read_log(last_create_game_line: usize) -> Result<LogData, std::io::Error> {
     // Figure out which player id we are. -1 means unknown.
     // We can also use this regex to find out where the game starts.
     let mut our_player_id = -1;
-    let player_id_re = Regex::new(r"^.*PlayerID=(\d+), PlayerName=lacker#1660.*$").unwrap();
+    let player_id_re = Regex::new(&format!(
+        r"^.*PlayerID=(\d+), PlayerName={}.*$",
+        regex::escape(&config.battletag)
+    ))
+    .unwrap();
 
     let card_id_re = Regex::new(r"^.*Updating Entity.* id=(\d+) .* CardID=(\w+).*$").unwrap();
+    let player_re = Regex::new(r"^.*Updating Entity.* player=(\d+)\] CardID=.*$").unwrap();
     let damage_re =
         Regex::new(r"^.*cardId=HERO_.*player=(\d+).*tag=DAMAGE value=(\d+).*$").unwrap();
     let armor_re = Regex::new(r"^.*cardId=HERO_.*player=(\d+).*tag=ARMOR value=(\d+).*$").unwrap();
     let cost_re =
         Regex::new(r"^.*TAG_CHANGE.*id=(\d+).*player=(\d+).*tag=COST value=(\d+).*$").unwrap();
     let renathal_re = Regex::new(r"^.*entityName=Prince Renathal.*tag=REVEALED.*$").unwrap();
+    // The game's turn counts both players' turns
+    let turn_re = Regex::new(r"^.*TAG_CHANGE Entity=GameEntity tag=TURN value=(\d+).*$").unwrap();
 
     let skip_n = log_data.last_create_game_line;
     let enum_lines = || lines.iter().enumerate().skip(skip_n);
@@ -65,6 +110,8 @@ fn read_log(last_create_game_line: usize) -> Result<LogData, std::io::Error> {
             log_data.opponent_armor = 0;
             card_id_map.clear();
             cost_map.clear();
+            log_data.seen.clear();
+            log_data.turn = 0;
             log_data.last_create_game_line = i;
             our_player_id = captures[1].parse().unwrap();
         }
@@ -72,6 +119,17 @@ fn read_log(last_create_game_line: usize) -> Result<LogData, std::io::Error> {
             let id = captures[1].parse::<i32>().unwrap();
             let card_id = &captures[2];
             card_id_map.insert(id, card_id.to_string());
+            let ours = player_re
+                .captures(line)
+                .is_some_and(|captures| captures[1].parse::<i32>().unwrap() == our_player_id);
+            let card = Card::from_card_id(card_id);
+            if ours && card != Card::Unknown {
+                log_data.seen.insert(id, card);
+            }
+        }
+        if let Some(captures) = turn_re.captures(line) {
+            let game_turn = captures[1].parse::<i32>().unwrap();
+            log_data.turn = (game_turn + 1) / 2;
         }
         if let Some(captures) = damage_re.captures(line) {
             // println!("damage line: {}", line);
@@ -102,7 +160,7 @@ fn read_log(last_create_game_line: usize) -> Result<LogData, std::io::Error> {
             let cost = captures[3].parse::<i32>().unwrap();
             cost_map.insert(id, cost);
         }
-        if let Some(_) = renathal_re.captures(line) {
+        if renathal_re.captures(line).is_some() {
             log_data.renathal = true;
         }
     }
@@ -134,14 +192,12 @@ fn read_log(last_create_game_line: usize) -> Result<LogData, std::io::Error> {
             return;
         }
         seen_ids.insert(id);
+        log_data.seen.insert(id, card);
         let mut ci = CardInstance::new(&card);
-        match cost_map.get(&id) {
-            Some(cost) => {
-                if card.cost() != UNKNOWN_COST {
-                    ci.cost_reduction = card.cost() - cost;
-                }
+        if let Some(cost) = cost_map.get(&id) {
+            if card.cost() != UNKNOWN_COST {
+                ci.cost_reduction = card.cost() - cost;
             }
-            None => (),
         }
         log_data.hand.push(ci);
     };
@@ -170,9 +226,11 @@ fn read_log(last_create_game_line: usize) -> Result<LogData, std::io::Error> {
     }
 
     // Find the mana
-    let mana_re =
-        Regex::new(r"^.*DebugPrintPower.*TAG_CHANGE Entity=lacker.*tag=RESOURCES value=(\d+).*$")
-            .unwrap();
+    let mana_re = Regex::new(&format!(
+        r"^.*DebugPrintPower.*TAG_CHANGE Entity={}.*tag=RESOURCES value=(\d+).*$",
+        regex::escape(config.name())
+    ))
+    .unwrap();
     for (_, line) in enum_lines() {
         if mana_re.is_match(line) {
             let caps = mana_re.captures(line).unwrap();
@@ -183,9 +241,23 @@ fn read_log(last_create_game_line: usize) -> Result<LogData, std::io::Error> {
     Ok(log_data)
 }
 
-fn current_game(log_data: &LogData) -> Game {
+// The cards left in the deck, taking out one copy of each card we've seen.
+// Seen cards that aren't in the deck, like The Coin, were generated.
+fn remaining_deck(deck: &[Card], log_data: &LogData) -> Vec<Card> {
+    let mut remaining = deck.to_vec();
+    for card in log_data.seen.values() {
+        if let Some(i) = remaining.iter().position(|c| c == card) {
+            remaining.remove(i);
+        }
+    }
+    remaining
+}
+
+fn current_game(log_data: &LogData, deck: &[Card]) -> Game {
     let mut game = Game::new();
     game.add_card_instances_to_hand(log_data.hand.clone().into_iter());
+    game.deck = remaining_deck(deck, log_data);
+    game.turn = log_data.turn;
     game.mana = log_data.mana;
     game.life = 30 - log_data.opponent_damage + log_data.opponent_armor;
     if log_data.renathal {
@@ -194,20 +266,81 @@ fn current_game(log_data: &LogData) -> Game {
     game
 }
 
+// Without lethal, suggest the move an exported policy likes best.
+// We don't read the board from the log, so the policy sees an empty one.
+#[cfg(feature = "mlp")]
+fn print_suggestion(policy: Option<&Mlp>, game: &Game) {
+    if let Some(policy) = policy {
+        let actions = game.non_kill_actions();
+        let (probs, _) = policy.predict(game, &actions);
+        let (action, p) = actions
+            .iter()
+            .zip(probs)
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .unwrap();
+        println!(
+            "policy suggests {} ({:.0}%)",
+            game.action_string(action),
+            100.0 * p
+        );
+    }
+}
+
+// Without lethal, estimate our chances of killing on later turns
+#[cfg(feature = "mlp")]
+fn print_kill_chances(win: Option<&WinMlp>, game: &Game) {
+    if let Some(win) = win {
+        println!(
+            "estimated P(kill by turn N): {}",
+            win.kill_chances_string(game)
+        );
+    }
+}
+
 fn main() {
-    println!("watching");
+    let args = Args::from_env_with_usage(USAGE);
+    let Some(battletag) = args.get_str("battletag") else {
+        usage_error("--battletag is required", USAGE);
+    };
+    let config = LogConfig {
+        path: args.get_str("log").unwrap_or(DEFAULT_LOG).to_string(),
+        battletag: battletag.to_string(),
+    };
+    let deck = parse_deck(args.get_str("deck").unwrap_or("panda"))
+        .unwrap_or_else(|e| usage_error(&e, USAGE));
+    let time_limit: f64 = args.get_or("time", 20.0);
+    #[cfg(feature = "mlp")]
+    let policy = args.get_str("mlp").map(|path| {
+        Mlp::load(path).unwrap_or_else(|e| usage_error(&format!("{}: {}", path, e), USAGE))
+    });
+    #[cfg(feature = "mlp")]
+    let win = args.get_str("win").map(|path| {
+        WinMlp::load(path).unwrap_or_else(|e| usage_error(&format!("{}: {}", path, e), USAGE))
+    });
+    #[cfg(not(feature = "mlp"))]
+    if args.has("mlp") || args.has("win") {
+        usage_error("--mlp and --win need the mlp feature", USAGE);
+    }
+
+    println!("watching {}", config.path);
     let mut previous_last_option_line = 0;
     let mut previous_last_create_game_line = 0;
     let mut last_mana = 0;
     loop {
-        if let Ok(log_data) = read_log(previous_last_create_game_line) {
+        if let Ok(log_data) = read_log(&config, previous_last_create_game_line) {
             if log_data.last_option_line > previous_last_option_line {
-                let game = current_game(&log_data);
+                let game = current_game(&log_data, &deck);
                 if game.mana != last_mana {
                     println!("\nhand: {}", game.hand_string());
-                    println!("mana: {}", log_data.mana);
+                    println!("turn: {}, mana: {}", game.turn, log_data.mana);
                     println!("opponent life: {}", game.life);
-                    game.print_deterministic_win(20.0);
+                    if !game.print_deterministic_win(time_limit) {
+                        #[cfg(feature = "mlp")]
+                        {
+                            print_suggestion(policy.as_ref(), &game);
+                            print_kill_chances(win.as_ref(), &game);
+                        }
+                    }
                 }
                 last_mana = game.mana;
             }
@@ -0,0 +1,56 @@
+use tch::Device;
+
+use goldfish::cli::Args;
+use goldfish::model::{default_device, TrainConfig, WinModel};
+use goldfish::selfplay::read_shards;
+
+const USAGE: &str = "\
+usage: winprob [options]
+
+Trains an estimator of P(kill by turn n) on how self-play games ended, and exports it
+for watch, play, and calibrate to run without libtorch.
+
+options:
+  --data <dir>          where to read shards from (default selfplay)
+  --out <path>          where to write the estimator (default win.mlp)
+  --epochs <n>          how many epochs to train (default 10)
+  --lr <x>              learning rate (default 0.001)
+  --batch-size <n>      samples per batch (default 256)
+  --cpu                 run on the CPU even if CUDA is available
+  --help                show this message
+";
+
+fn main() {
+    let args = Args::from_env_with_usage(USAGE);
+    let data = args.get_str("data").unwrap_or("selfplay");
+    let out = args.get_str("out").unwrap_or("win.mlp");
+    let epochs: usize = args.get_or("epochs", 10);
+    let device = if args.has("cpu") {
+        Device::Cpu
+    } else {
+        default_device()
+    };
+    let defaults = TrainConfig::default();
+    let config = TrainConfig {
+        learning_rate: args.get_or("lr", defaults.learning_rate),
+        batch_size: args.get_or("batch-size", defaults.batch_size),
+        ..defaults
+    };
+
+    let samples = read_shards(data).unwrap();
+    println!(
+        "training on {} samples from {} on {:?}",
+        samples.len(),
+        data,
+        device
+    );
+
+    let model = WinModel::new(device);
+    let mut optimizer = model.optimizer(&config);
+    for epoch in 1..=epochs {
+        let loss = model.train_epoch(&mut optimizer, &samples, &config);
+        println!("epoch {}: loss {:.4}", epoch, loss);
+    }
+    model.to_mlp().save(out).unwrap();
+    println!("saved {}", out);
+}
@@ -2,6 +2,7 @@ use enum_iterator::Sequence;
 use lazy_static::lazy_static;
 use std::collections::HashMap;
 use std::fmt;
+use std::hash::{Hash, Hasher};
 
 pub const UNKNOWN_COST: i32 = 20;
 
@@ -32,7 +33,7 @@ pub enum Card {
 }
 
 // A Redditor named something like "Panda" recommended this, but I lost the link
-pub const PANDA_DECK: &'static [Card] = &[
+pub const PANDA_DECK: &[Card] = &[
     Card::Coin,
     Card::Coin,
     Card::Preparation,
@@ -66,11 +67,16 @@ pub const PANDA_DECK: &'static [Card] = &[
 ];
 
 lazy_static! {
+    // Every name a card goes by, lowercased: the full name, the short name in the code,
+    // and the other names the log file uses
     static ref CARD_FOR_NAME: HashMap<String, Card> = {
         let mut m = HashMap::new();
         for card in enum_iterator::all::<Card>() {
-            m.insert(card.to_string(), card);
+            m.insert(card.to_string().to_lowercase(), card);
+            m.insert(format!("{:?}", card).to_lowercase(), card);
         }
+        m.insert("bananas".to_string(), Card::Unknown);
+        m.insert("counterfeit coin".to_string(), Card::Coin);
         m
     };
 }
@@ -107,17 +113,15 @@ impl fmt::Display for Card {
 impl Card {
     // Must match the log file output
     pub fn from_name(s: &str) -> Self {
-        if let Some(card) = CARD_FOR_NAME.get(s) {
-            return *card;
-        }
-        match s {
-            "Bananas" => Card::Unknown,
-            "Counterfeit Coin" => Card::Coin,
-            _ => {
-                println!("unknown card name: {}", s);
-                Card::Unknown
-            }
-        }
+        Card::parse(s).unwrap_or_else(|| {
+            println!("unknown card name: {}", s);
+            Card::Unknown
+        })
+    }
+
+    // Parses any name the card goes by, ignoring case
+    pub fn parse(s: &str) -> Option<Self> {
+        CARD_FOR_NAME.get(&s.trim().to_lowercase()).copied()
     }
 
     pub fn from_card_id(card_id: &str) -> Self {
@@ -177,73 +181,87 @@ impl Card {
     }
 
     pub fn minion(&self) -> bool {
-        match self {
-            Card::Dancer => true,
-            Card::Foxy => true,
-            Card::Pillager => true,
-            Card::Scabbs => true,
-            Card::Shark => true,
-            Card::Tenwu => true,
-            _ => false,
-        }
+        matches!(
+            self,
+            Card::Dancer | Card::Foxy | Card::Pillager | Card::Scabbs | Card::Shark | Card::Tenwu
+        )
     }
 
     pub fn spell(&self) -> bool {
-        match self {
-            Card::BoneSpike => true,
-            Card::Cloak => true,
-            Card::Coin => true,
-            Card::Door => true,
-            Card::Evasion => true,
-            Card::Extortion => true,
-            Card::GoneFishin => true,
-            Card::Potion => true,
-            Card::Preparation => true,
-            Card::SecretPassage => true,
-            Card::Shadowstep => true,
-            Card::Shroud => true,
-            Card::Swindle => true,
-            _ => false,
-        }
+        matches!(
+            self,
+            Card::BoneSpike
+                | Card::Cloak
+                | Card::Coin
+                | Card::Door
+                | Card::Evasion
+                | Card::Extortion
+                | Card::GoneFishin
+                | Card::Potion
+                | Card::Preparation
+                | Card::SecretPassage
+                | Card::Shadowstep
+                | Card::Shroud
+                | Card::Swindle
+        )
     }
 
     pub fn weapon(&self) -> bool {
-        match self {
-            Card::Cutlass => true,
-            _ => false,
-        }
+        matches!(self, Card::Cutlass)
     }
 
     pub fn combo(&self) -> bool {
-        match self {
-            Card::Pillager => true,
-            Card::Scabbs => true,
-            _ => false,
-        }
+        matches!(self, Card::Pillager | Card::Scabbs)
     }
 
     pub fn must_target(&self) -> bool {
-        match self {
-            Card::Shadowstep => true,
-            Card::Tenwu => true,
-            _ => false,
-        }
+        matches!(self, Card::Shadowstep | Card::Tenwu)
     }
 
     pub fn is_trade(&self) -> bool {
         self == &Card::Cutlass || self == &Card::Extortion
     }
+
+    pub fn legendary(&self) -> bool {
+        matches!(self, Card::Foxy | Card::Scabbs | Card::Shark | Card::Tenwu)
+    }
+
+    // How many copies a constructed deck can have
+    pub fn max_copies(&self) -> usize {
+        if self.legendary() {
+            1
+        } else {
+            2
+        }
+    }
 }
 
 // Properties that apply to only the specific version of this card, in our hand.
 // This could extend to on-board properties later.
-#[derive(Copy, Clone, Eq, Hash, PartialEq)]
+#[derive(Copy, Clone)]
 pub struct CardInstance {
     pub card: Card,
     pub potion: bool,        // whether this card was created with potion
     pub tenwu: bool,         // whether this card was bounced with tenwu
     pub cost_reduction: i32, // other cost reduction
     pub passage: bool,       // whether this card was drawn with Secret Passage
+    pub from_deck: bool,     // whether this copy was drawn from the deck, for card statistics
+}
+
+// Where a copy came from doesn't change how it plays, so equality and hashing leave it out,
+// like they leave out the game's card statistics
+impl PartialEq for CardInstance {
+    fn eq(&self, other: &CardInstance) -> bool {
+        self.play_key() == other.play_key()
+    }
+}
+
+impl Eq for CardInstance {}
+
+impl Hash for CardInstance {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.play_key().hash(state);
+    }
 }
 
 impl fmt::Display for CardInstance {
@@ -271,6 +289,7 @@ impl CardInstance {
             tenwu: false,
             cost_reduction: 0,
             passage: false,
+            from_deck: false,
         }
     }
 
@@ -282,6 +301,51 @@ impl CardInstance {
         };
         std::cmp::max(0, base - self.cost_reduction)
     }
+    // Everything that affects how this copy plays
+    fn play_key(&self) -> (Card, bool, bool, i32, bool) {
+        (
+            self.card,
+            self.potion,
+            self.tenwu,
+            self.cost_reduction,
+            self.passage,
+        )
+    }
+}
+
+// Parses a deck, either "panda" or a comma-separated list of card names
+pub fn parse_deck(s: &str) -> Result<Vec<Card>, String> {
+    if s.eq_ignore_ascii_case("panda") {
+        return Ok(PANDA_DECK.to_vec());
+    }
+    s.split(',')
+        .filter(|name| !name.trim().is_empty())
+        .map(|name| Card::parse(name).ok_or(format!("unknown card: {}", name.trim())))
+        .collect()
+}
+
+pub const DECK_SIZE: usize = 30;
+
+// Checks the deckbuilding rules: exactly 30 cards, with at most 2 copies of each card,
+// or 1 of each legendary
+pub fn check_deck(deck: &[Card]) -> Result<(), String> {
+    if deck.len() != DECK_SIZE {
+        return Err(format!(
+            "decks need {} cards, not {}",
+            DECK_SIZE,
+            deck.len()
+        ));
+    }
+    for card in enum_iterator::all::<Card>() {
+        let copies = deck.iter().filter(|c| **c == card).count();
+        if card == Card::Unknown && copies > 0 {
+            return Err("decks can't contain unknown cards".to_string());
+        }
+        if copies > card.max_copies() {
+            return Err(format!("too many copies of {}: {}", card, copies));
+        }
+    }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -312,4 +376,37 @@ mod tests {
     fn deck_length() {
         assert_eq!(PANDA_DECK.len(), 30);
     }
+
+    #[test]
+    fn card_names() {
+        for card in enum_iterator::all::<Card>() {
+            assert_eq!(Card::parse(&card.to_string()), Some(card));
+            assert_eq!(Card::parse(&format!("{:?}", card)), Some(card));
+        }
+        assert_eq!(Card::parse(" spectral pillager"), Some(Card::Pillager));
+        assert_eq!(Card::from_name("Counterfeit Coin"), Card::Coin);
+        assert_eq!(Card::parse("Leeroy"), None);
+    }
+
+    #[test]
+    fn parsing_decks() {
+        assert_eq!(parse_deck("panda").unwrap(), PANDA_DECK.to_vec());
+        assert_eq!(
+            parse_deck("foxy, Scabbs Cutterbutter,the coin").unwrap(),
+            vec![Card::Foxy, Card::Scabbs, Card::Coin]
+        );
+        assert!(parse_deck("Foxy,Leeroy").is_err());
+    }
+
+    #[test]
+    fn deck_rules() {
+        assert_eq!(check_deck(PANDA_DECK), Ok(()));
+        assert!(check_deck(&PANDA_DECK[1..]).is_err());
+        let mut two_foxies = PANDA_DECK.to_vec();
+        two_foxies[0] = Card::Foxy;
+        assert!(check_deck(&two_foxies).is_err());
+        let mut extra_dancer = PANDA_DECK.to_vec();
+        extra_dancer[0] = Card::Dancer;
+        assert_eq!(check_deck(&extra_dancer), Ok(()));
+    }
 }
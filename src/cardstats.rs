@@ -0,0 +1,191 @@
+use std::fmt::Write;
+use std::hash::{Hash, Hasher};
+
+use enum_iterator::Sequence;
+
+use crate::card::Card;
+
+// What happened to the copies of one card
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct CardCounts {
+    pub drawn: u32,  // drawn from the deck, or for generated copies, how many were made
+    pub played: u32, // played from hand
+    pub winning_line: u32, // played in the line that the solver found for the kill
+    pub stranded: u32, // still in hand when the game ended
+    pub burned: u32, // lost to a full hand
+}
+
+impl CardCounts {
+    fn add(&mut self, other: &CardCounts) {
+        self.drawn += other.drawn;
+        self.played += other.played;
+        self.winning_line += other.winning_line;
+        self.stranded += other.stranded;
+        self.burned += other.burned;
+    }
+}
+
+// Counts for every card, over one game or many.
+// Copies from the deck are counted apart from generated copies, like The Coin, Potion of
+// Illusion copies, and minions that Shadowstep or Tenwu bounce, so that the deck's counts
+// all have the same denominator.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CardStats {
+    deck: [CardCounts; Card::CARDINALITY],
+    generated: [CardCounts; Card::CARDINALITY],
+}
+
+impl Default for CardStats {
+    fn default() -> CardStats {
+        CardStats {
+            deck: [CardCounts::default(); Card::CARDINALITY],
+            generated: [CardCounts::default(); Card::CARDINALITY],
+        }
+    }
+}
+
+impl CardStats {
+    // The counts for copies from the deck
+    pub fn get(&self, card: Card) -> &CardCounts {
+        &self.deck[card as usize]
+    }
+
+    pub fn get_mut(&mut self, card: Card) -> &mut CardCounts {
+        &mut self.deck[card as usize]
+    }
+
+    pub fn get_generated(&self, card: Card) -> &CardCounts {
+        &self.generated[card as usize]
+    }
+
+    pub fn get_generated_mut(&mut self, card: Card) -> &mut CardCounts {
+        &mut self.generated[card as usize]
+    }
+
+    pub fn add(&mut self, other: &CardStats) {
+        let counts = self.deck.iter_mut().chain(self.generated.iter_mut());
+        for (a, b) in counts.zip(other.deck.iter().chain(&other.generated)) {
+            a.add(b);
+        }
+    }
+
+    // Per-game averages for each card that showed up at all, over num_games games,
+    // for copies from the deck and then for generated copies
+    pub fn table(&self, num_games: usize) -> String {
+        let mut out = String::new();
+        for (counts, title, first_column) in [
+            (&self.deck, "from the deck", "drawn"),
+            (&self.generated, "generated", "made"),
+        ] {
+            if counts.iter().all(|c| *c == CardCounts::default()) {
+                continue;
+            }
+            writeln!(
+                out,
+                "{:<24} {:>6} {:>6} {:>6} {:>8} {:>6}",
+                title, first_column, "played", "in win", "stranded", "burned"
+            )
+            .unwrap();
+            let per_game = |n: u32| n as f64 / num_games.max(1) as f64;
+            for card in enum_iterator::all::<Card>() {
+                let c = &counts[card as usize];
+                if *c == CardCounts::default() {
+                    continue;
+                }
+                writeln!(
+                    out,
+                    "{:<24} {:>6.2} {:>6.2} {:>6.2} {:>8.2} {:>6.2}",
+                    card.to_string(),
+                    per_game(c.drawn),
+                    per_game(c.played),
+                    per_game(c.winning_line),
+                    per_game(c.stranded),
+                    per_game(c.burned)
+                )
+                .unwrap();
+            }
+        }
+        out
+    }
+
+    // The totals as a JSON object, keyed by card name, for each card that showed up at all
+    pub fn to_json(&self) -> String {
+        let object = |counts: &[CardCounts; Card::CARDINALITY]| -> String {
+            let cards: Vec<String> = enum_iterator::all::<Card>()
+                .filter(|card| counts[*card as usize] != CardCounts::default())
+                .map(|card| {
+                    let c = &counts[card as usize];
+                    format!(
+                        "\"{}\": {{\"drawn\": {}, \"played\": {}, \"winning_line\": {}, \
+                         \"stranded\": {}, \"burned\": {}}}",
+                        card, c.drawn, c.played, c.winning_line, c.stranded, c.burned
+                    )
+                })
+                .collect();
+            format!("{{{}}}", cards.join(", "))
+        };
+        format!(
+            "{{\"deck\": {}, \"generated\": {}}}",
+            object(&self.deck),
+            object(&self.generated)
+        )
+    }
+}
+
+// Card statistics that a game can carry along while it's played.
+// It's left out of equality and hashing, so that tracking never changes how search sees
+// a game.
+#[derive(Clone, Debug, Default)]
+pub struct Tally(pub Option<Box<CardStats>>);
+
+impl Tally {
+    pub fn record(&mut self, card: Card, from_deck: bool, f: impl FnOnce(&mut CardCounts)) {
+        if let Some(stats) = &mut self.0 {
+            if from_deck {
+                f(stats.get_mut(card));
+            } else {
+                f(stats.get_generated_mut(card));
+            }
+        }
+    }
+}
+
+impl PartialEq for Tally {
+    fn eq(&self, _: &Tally) -> bool {
+        true
+    }
+}
+
+impl Eq for Tally {}
+
+impl Hash for Tally {
+    fn hash<H: Hasher>(&self, _: &mut H) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adding_and_tables() {
+        let mut a = CardStats::default();
+        a.get_mut(Card::Foxy).drawn = 1;
+        let mut b = CardStats::default();
+        b.get_mut(Card::Foxy).drawn = 2;
+        b.get_mut(Card::Foxy).burned = 1;
+        b.get_generated_mut(Card::Coin).played = 1;
+        a.add(&b);
+        assert_eq!(a.get(Card::Foxy).drawn, 3);
+        assert_eq!(a.get(Card::Foxy).burned, 1);
+        assert_eq!(a.get_generated(Card::Coin).played, 1);
+        assert_eq!(a.get(Card::Coin).played, 0);
+        let table = a.table(2);
+        assert!(table.contains("Foxy Fraud"));
+        assert!(table.contains("1.50"));
+        assert!(table.contains("generated"));
+        assert!(!table.contains("Shadowstep"));
+        let json = a.to_json();
+        assert!(json.contains("\"deck\": {\"Foxy Fraud\": {\"drawn\": 3, \"played\": 0"));
+        assert!(json.contains("\"generated\": {\"The Coin\": {\"drawn\": 0, \"played\": 1"));
+    }
+}
@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+use std::env;
+use std::fmt::Debug;
+use std::process;
+use std::str::FromStr;
+
+// A minimal parser for "--name value", "--name=value", and "--flag" style arguments
+pub struct Args {
+    values: HashMap<String, String>,
+    flags: Vec<String>,
+    usage: String, // printed along with any problem with the arguments
+}
+
+impl Args {
+    // Reads the command line, printing the usage and exiting for --help or for anything
+    // we can't parse, like an unknown option.
+    // The known options are the ones listed in the usage as "--name".
+    pub fn from_env_with_usage(usage: &str) -> Args {
+        let mut args = Args::parse(env::args().skip(1)).unwrap_or_else(|e| usage_error(&e, usage));
+        args.usage = usage.to_string();
+        if args.has("help") {
+            print!("{}", usage);
+            process::exit(0);
+        }
+        if let Some(name) = args.unknown(usage) {
+            usage_error(&format!("unknown option: --{}", name), usage);
+        }
+        args
+    }
+
+    // The first option that the usage doesn't mention, if any
+    fn unknown(&self, usage: &str) -> Option<&str> {
+        let known: Vec<&str> = usage
+            .split_whitespace()
+            .filter_map(|word| word.strip_prefix("--"))
+            .collect();
+        self.flags
+            .iter()
+            .chain(self.values.keys())
+            .map(|name| name.as_str())
+            .find(|name| !known.contains(name))
+    }
+
+    pub fn parse(args: impl Iterator<Item = String>) -> Result<Args, String> {
+        let mut values = HashMap::new();
+        let mut flags = Vec::new();
+        let mut args = args.peekable();
+        while let Some(arg) = args.next() {
+            let name = match arg.strip_prefix("--") {
+                Some(name) => name,
+                None => return Err(format!("unexpected argument: {}", arg)),
+            };
+            if let Some((name, value)) = name.split_once('=') {
+                values.insert(name.to_string(), value.to_string());
+            } else if args.peek().is_some_and(|next| !next.starts_with("--")) {
+                values.insert(name.to_string(), args.next().unwrap());
+            } else {
+                flags.push(name.to_string());
+            }
+        }
+        Ok(Args {
+            values,
+            flags,
+            usage: String::new(),
+        })
+    }
+
+    pub fn has(&self, name: &str) -> bool {
+        self.flags.iter().any(|f| f == name) || self.values.contains_key(name)
+    }
+
+    pub fn get_str(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(|s| s.as_str())
+    }
+
+    // Prints the usage and exits if the value doesn't parse
+    pub fn get<T: FromStr>(&self, name: &str) -> Option<T>
+    where
+        T::Err: Debug,
+    {
+        self.try_get(name)
+            .unwrap_or_else(|e| usage_error(&e, &self.usage))
+    }
+
+    pub fn try_get<T: FromStr>(&self, name: &str) -> Result<Option<T>, String>
+    where
+        T::Err: Debug,
+    {
+        match self.get_str(name) {
+            Some(s) => match s.parse() {
+                Ok(value) => Ok(Some(value)),
+                Err(e) => Err(format!("bad value for --{}: {} ({:?})", name, s, e)),
+            },
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_or<T: FromStr>(&self, name: &str, default: T) -> T
+    where
+        T::Err: Debug,
+    {
+        self.get(name).unwrap_or(default)
+    }
+}
+
+// Prints a problem with the command line along with the usage, and exits
+pub fn usage_error(message: &str, usage: &str) -> ! {
+    eprintln!("{}\n\n{}", message, usage);
+    process::exit(2);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Args {
+        Args::parse(s.split_whitespace().map(|s| s.to_string())).unwrap()
+    }
+
+    #[test]
+    fn values_and_flags() {
+        let args = parse("--games 10 --quiet --deck=panda --time 2.5");
+        assert_eq!(args.get::<usize>("games"), Some(10));
+        assert_eq!(args.get_str("deck"), Some("panda"));
+        assert_eq!(args.get_or("time", 1.0), 2.5);
+        assert_eq!(args.get_or("seed", 7), 7);
+        assert!(args.has("quiet"));
+        assert!(!args.has("verbose"));
+    }
+
+    #[test]
+    fn bad_arguments() {
+        let positional = Args::parse(["--games", "10", "oops"].iter().map(|s| s.to_string()));
+        assert!(positional.is_err());
+        let args = parse("--games ten");
+        assert!(args.try_get::<usize>("games").is_err());
+        assert_eq!(args.try_get::<usize>("seed"), Ok(None));
+    }
+
+    #[test]
+    fn unknown_options() {
+        let usage = "usage: evaluate [options]\n  --games <n>   games to play\n  --quiet\n";
+        assert_eq!(parse("--games 10 --quiet").unknown(usage), None);
+        assert_eq!(parse("--games 10 --gmaes 3").unknown(usage), Some("gmaes"));
+    }
+}
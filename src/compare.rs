@@ -0,0 +1,488 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::agent::{agent_from_name, Agent};
+use crate::card::Card;
+use crate::cardstats::CardStats;
+use crate::sim::{play_seeded_continuation, GameResult, Limits};
+use crate::stats::{median, paired_difference, Summary};
+
+// The results of several agents playing the same seeded games, so that each agent
+// sees the same openings and draws
+pub struct Comparison {
+    pub names: Vec<String>,
+    pub seeds: Vec<u64>,
+    pub results: Vec<Vec<GameResult>>, // for each agent, a result for each seed
+}
+
+// Escapes a string for use inside JSON quotes
+fn json_string(s: &str) -> String {
+    let mut escaped = String::new();
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => write!(escaped, "\\u{:04x}", c as u32).unwrap(),
+            c => escaped.push(c),
+        }
+    }
+    format!("\"{}\"", escaped)
+}
+
+// Quotes a CSV field if it needs it
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+impl Comparison {
+    pub fn new(names: Vec<String>, seeds: Vec<u64>, results: Vec<Vec<GameResult>>) -> Comparison {
+        assert_eq!(names.len(), results.len());
+        assert!(results.iter().all(|r| r.len() == seeds.len()));
+        Comparison {
+            names,
+            seeds,
+            results,
+        }
+    }
+
+    // The kill turn of each game, counting games we never won as the turn limit
+    pub fn kill_turns(&self, agent: usize) -> Vec<f64> {
+        self.results[agent]
+            .iter()
+            .map(|r| r.kill_turn as f64)
+            .collect()
+    }
+
+    pub fn summary(&self, agent: usize) -> Summary {
+        Summary::new(&self.kill_turns(agent))
+    }
+
+    pub fn median(&self, agent: usize) -> f64 {
+        median(&self.kill_turns(agent))
+    }
+
+    pub fn win_rate(&self, agent: usize) -> f64 {
+        let wins = self.results[agent].iter().filter(|r| r.won).count();
+        wins as f64 / self.seeds.len().max(1) as f64
+    }
+
+    // How many games ended on each turn, with losses under the turn limit
+    pub fn histogram(&self, agent: usize) -> BTreeMap<i32, usize> {
+        let mut histogram = BTreeMap::new();
+        for r in &self.results[agent] {
+            *histogram.entry(r.kill_turn).or_insert(0) += 1;
+        }
+        histogram
+    }
+
+    // How many games we won on each turn
+    pub fn wins_by_turn(&self, agent: usize) -> BTreeMap<i32, usize> {
+        let mut histogram = BTreeMap::new();
+        for r in self.results[agent].iter().filter(|r| r.won) {
+            *histogram.entry(r.kill_turn).or_insert(0) += 1;
+        }
+        histogram
+    }
+
+    // What happened to each card, summed over the agent's games
+    pub fn card_stats(&self, agent: usize) -> CardStats {
+        let mut stats = CardStats::default();
+        for r in &self.results[agent] {
+            stats.add(&r.cards);
+        }
+        stats
+    }
+
+    // Per-game card statistics for each agent
+    pub fn card_table(&self) -> String {
+        let mut out = String::new();
+        for (i, name) in self.names.iter().enumerate() {
+            writeln!(out, "\ncards per game for [{}] {}:", i, name).unwrap();
+            out.push_str(&self.card_stats(i).table(self.seeds.len()));
+        }
+        out
+    }
+
+    // The kill turns of agent a minus those of agent b, paired by seed.
+    // Negative means a kills faster.
+    pub fn paired(&self, a: usize, b: usize) -> Summary {
+        paired_difference(&self.kill_turns(a), &self.kill_turns(b))
+    }
+
+    // A human-readable report with summaries, histograms, and paired tests
+    pub fn table(&self) -> String {
+        let mut out = String::new();
+        let n = self.names.len();
+        let width = self.names.iter().map(|s| s.len()).max().unwrap_or(0).max(5);
+        writeln!(
+            out,
+            "{:<4} {:<width$} {:>6} {:>6} {:>17} {:>7} {:>6}",
+            "",
+            "agent",
+            "games",
+            "mean",
+            "95% CI",
+            "median",
+            "wins",
+            width = width
+        )
+        .unwrap();
+        for i in 0..n {
+            let s = self.summary(i);
+            let (low, high) = s.confidence_interval();
+            writeln!(
+                out,
+                "{:<4} {:<width$} {:>6} {:>6.2} {:>17} {:>7.1} {:>5.0}%",
+                format!("[{}]", i),
+                self.names[i],
+                s.n,
+                s.mean,
+                format!("{:.2} to {:.2}", low, high),
+                self.median(i),
+                100.0 * self.win_rate(i),
+                width = width
+            )
+            .unwrap();
+        }
+
+        // The turn histogram for each agent, with the cumulative chance of winning by that turn
+        writeln!(out, "\nwins by turn, with cumulative win rate:").unwrap();
+        let header: Vec<String> = (0..n)
+            .map(|i| format!("{:>12}", format!("[{}]", i)))
+            .collect();
+        writeln!(out, "turn {}", header.join("")).unwrap();
+        let histograms: Vec<BTreeMap<i32, usize>> = (0..n).map(|i| self.wins_by_turn(i)).collect();
+        let last_turn = histograms
+            .iter()
+            .filter_map(|h| h.keys().last())
+            .max()
+            .copied()
+            .unwrap_or(0);
+        let mut cumulative = vec![0; n];
+        for turn in 1..=last_turn {
+            let mut row = format!("{:>4} ", turn);
+            for (i, histogram) in histograms.iter().enumerate() {
+                let games = histogram.get(&turn).copied().unwrap_or(0);
+                cumulative[i] += games;
+                let percent = 100.0 * cumulative[i] as f64 / self.seeds.len().max(1) as f64;
+                write!(row, "{:>5} ({:>3.0}%)", games, percent).unwrap();
+            }
+            writeln!(out, "{}", row).unwrap();
+        }
+        let mut row = "lost ".to_string();
+        for i in 0..n {
+            let lost = self.results[i].iter().filter(|r| !r.won).count();
+            write!(row, "{:>12}", lost).unwrap();
+        }
+        writeln!(out, "{}", row).unwrap();
+
+        if n > 1 {
+            writeln!(
+                out,
+                "\npaired differences in kill turn, where negative is faster:"
+            )
+            .unwrap();
+            for a in 0..n {
+                for b in a + 1..n {
+                    let d = self.paired(b, a);
+                    writeln!(out, "[{}] - [{}]: {}, p = {:.3}", b, a, d, d.p_value()).unwrap();
+                }
+            }
+        }
+        out
+    }
+
+    pub fn to_json(&self) -> String {
+        let n = self.names.len();
+        let agents: Vec<String> = (0..n)
+            .map(|i| {
+                let s = self.summary(i);
+                let (low, high) = s.confidence_interval();
+                let histogram: Vec<String> = self
+                    .histogram(i)
+                    .iter()
+                    .map(|(turn, games)| format!("\"{}\": {}", turn, games))
+                    .collect();
+                let turns: Vec<String> = self.results[i]
+                    .iter()
+                    .map(|r| r.kill_turn.to_string())
+                    .collect();
+                format!(
+                    "{{\"name\": {}, \"games\": {}, \"mean\": {}, \"std_error\": {}, \
+                     \"ci_low\": {}, \"ci_high\": {}, \"median\": {}, \"win_rate\": {}, \
+                     \"histogram\": {{{}}}, \"kill_turns\": [{}], \"cards\": {}}}",
+                    json_string(&self.names[i]),
+                    s.n,
+                    s.mean,
+                    s.std_error,
+                    low,
+                    high,
+                    self.median(i),
+                    self.win_rate(i),
+                    histogram.join(", "),
+                    turns.join(", "),
+                    self.card_stats(i).to_json()
+                )
+            })
+            .collect();
+        let mut pairs = Vec::new();
+        for a in 0..n {
+            for b in a + 1..n {
+                let d = self.paired(b, a);
+                let (low, high) = d.confidence_interval();
+                pairs.push(format!(
+                    "{{\"a\": {}, \"b\": {}, \"mean_difference\": {}, \"std_error\": {}, \
+                     \"ci_low\": {}, \"ci_high\": {}, \"p_value\": {}}}",
+                    json_string(&self.names[b]),
+                    json_string(&self.names[a]),
+                    d.mean,
+                    d.std_error,
+                    low,
+                    high,
+                    d.p_value()
+                ));
+            }
+        }
+        let seeds: Vec<String> = self.seeds.iter().map(|s| s.to_string()).collect();
+        format!(
+            "{{\n  \"seeds\": [{}],\n  \"agents\": [\n    {}\n  ],\n  \"paired\": [\n    {}\n  ]\n}}\n",
+            seeds.join(", "),
+            agents.join(",\n    "),
+            pairs.join(",\n    ")
+        )
+    }
+
+    // One row per game, for analysis elsewhere
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from("agent,seed,won,kill_turn\n");
+        for (name, results) in self.names.iter().zip(&self.results) {
+            for (seed, r) in self.seeds.iter().zip(results) {
+                writeln!(
+                    out,
+                    "{},{},{},{}",
+                    csv_field(name),
+                    seed,
+                    r.won,
+                    r.kill_turn
+                )
+                .unwrap();
+            }
+        }
+        out
+    }
+}
+
+// Plays every named agent on every seed, spread over the provided number of threads.
+// Agents aren't shared between threads, so each thread builds its own from the names.
+// Every game reseeds the randomness for both the game and the agent, so the results don't
+// depend on how many threads there are. That only holds when the solver is limited by
+// nodes, since a time limit cuts searches short depending on the machine's load.
+// Calls report with the agent index, the seed, and the result as each game finishes.
+pub fn run_comparison(
+    deck: &[Card],
+    names: &[String],
+    seeds: &[u64],
+    limits: &Limits,
+    threads: usize,
+    report: &(dyn Fn(usize, u64, &GameResult) + Sync),
+) -> Result<Comparison, String> {
+    let games: Vec<(u64, u64)> = seeds.iter().map(|seed| (*seed, 0)).collect();
+    let results = run_games(deck, names, &games, limits, threads, report)?;
+    Ok(Comparison::new(names.to_vec(), seeds.to_vec(), results))
+}
+
+// Plays each (seed, continuation) game with each named agent, like run_comparison.
+// Returns a result for each game, for each agent.
+pub fn run_games(
+    deck: &[Card],
+    names: &[String],
+    games: &[(u64, u64)],
+    limits: &Limits,
+    threads: usize,
+    report: &(dyn Fn(usize, u64, &GameResult) + Sync),
+) -> Result<Vec<Vec<GameResult>>, String> {
+    // Check the names up front, so that the threads can't fail
+    for name in names {
+        agent_from_name(name)?;
+    }
+    let new_agents = || {
+        names
+            .iter()
+            .map(|name| agent_from_name(name).unwrap())
+            .collect()
+    };
+    Ok(run_agents(
+        deck,
+        &new_agents,
+        games,
+        limits,
+        threads,
+        report,
+    ))
+}
+
+// Like run_games, with agents from new_agents, which each thread calls once
+pub fn run_agents(
+    deck: &[Card],
+    new_agents: &(dyn Fn() -> Vec<Box<dyn Agent>> + Sync),
+    games: &[(u64, u64)],
+    limits: &Limits,
+    threads: usize,
+    report: &(dyn Fn(usize, u64, &GameResult) + Sync),
+) -> Vec<Vec<GameResult>> {
+    let num_agents = new_agents().len();
+
+    // Interleave the agents, so that progress is even across them
+    let num_games = num_agents * games.len();
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(vec![vec![None; games.len()]; num_agents]);
+    thread::scope(|scope| {
+        for _ in 0..threads.max(1) {
+            scope.spawn(|| {
+                let mut agents = new_agents();
+                loop {
+                    let job = next.fetch_add(1, Ordering::SeqCst);
+                    if job >= num_games {
+                        break;
+                    }
+                    let (agent, game) = (job % num_agents, job / num_agents);
+                    let (seed, continuation) = games[game];
+                    let result = play_seeded_continuation(
+                        deck,
+                        seed,
+                        continuation,
+                        agents[agent].as_mut(),
+                        limits,
+                    );
+                    report(agent, seed, &result);
+                    results.lock().unwrap()[agent][game] = Some(result);
+                }
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|r| r.into_iter().map(|r| r.unwrap()).collect())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::{MctsAgent, SolverFirstAgent};
+    use crate::game::SolverLimit;
+    use crate::mcts::{escape_policy, MAX_TURNS, MCTS};
+
+    fn result(kill_turn: i32) -> GameResult {
+        let mut cards = CardStats::default();
+        cards.get_mut(Card::Foxy).drawn = 1;
+        GameResult {
+            won: kill_turn < MAX_TURNS,
+            kill_turn,
+            cards,
+        }
+    }
+
+    fn comparison() -> Comparison {
+        Comparison::new(
+            vec!["slow".to_string(), "fast, \"really\"".to_string()],
+            vec![1, 2, 3, 4],
+            vec![
+                vec![result(6), result(7), result(10), result(5)],
+                vec![result(5), result(6), result(8), result(5)],
+            ],
+        )
+    }
+
+    #[test]
+    fn statistics() {
+        let c = comparison();
+        assert_eq!(c.summary(0).mean, 7.0);
+        assert_eq!(c.median(1), 5.5);
+        assert_eq!(c.win_rate(0), 0.75);
+        assert_eq!(c.histogram(1)[&5], 2);
+        assert_eq!(c.wins_by_turn(0).get(&10), None);
+        assert_eq!(c.paired(1, 0).mean, -1.0);
+    }
+
+    #[test]
+    fn threads_do_not_change_results() {
+        let names = vec!["escape".to_string(), "random".to_string()];
+        let seeds: Vec<u64> = (10..16).collect();
+        let run = |threads| {
+            run_comparison(
+                crate::card::PANDA_DECK,
+                &names,
+                &seeds,
+                &Limits::default(),
+                threads,
+                &|_, _, _| (),
+            )
+            .unwrap()
+            .results
+        };
+        assert_eq!(run(1), run(3));
+
+        // Searching agents too, with a small search so that the test is quick
+        let new_agents = || -> Vec<Box<dyn Agent>> {
+            let mcts = || MCTS::new(escape_policy).with_solver_nodes(1000);
+            vec![
+                Box::new(MctsAgent::new(10, mcts)),
+                Box::new(SolverFirstAgent::new(
+                    Box::new(MctsAgent::new(10, mcts)),
+                    SolverLimit::Nodes(10_000),
+                )),
+            ]
+        };
+        let games: Vec<(u64, u64)> = (10..12).map(|seed| (seed, 0)).collect();
+        let limits = Limits {
+            max_turns: 4,
+            ..Limits::default()
+        };
+        let run = |threads| {
+            run_agents(
+                crate::card::PANDA_DECK,
+                &new_agents,
+                &games,
+                &limits,
+                threads,
+                &|_, _, _| (),
+            )
+        };
+        assert_eq!(run(1), run(3));
+        assert!(run_comparison(
+            crate::card::PANDA_DECK,
+            &["nobody".to_string()],
+            &seeds,
+            &Limits::default(),
+            2,
+            &|_, _, _| ()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn outputs() {
+        let c = comparison();
+        let table = c.table();
+        assert!(table.contains("[1] - [0]"));
+        assert!(table.contains("lost            1           0"));
+        let json = c.to_json();
+        assert!(json.contains("\"fast, \\\"really\\\"\""));
+        assert!(json.contains("\"kill_turns\": [6, 7, 10, 5]"));
+        assert!(json.contains("\"cards\": {\"deck\": {\"Foxy Fraud\": {\"drawn\": 4,"));
+        let csv = c.to_csv();
+        assert_eq!(csv.lines().count(), 9);
+        assert!(csv.contains("\"fast, \"\"really\"\"\",3,true,8"));
+    }
+}
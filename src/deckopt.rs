@@ -0,0 +1,171 @@
+use rand::seq::SliceRandom;
+
+use crate::card::{check_deck, Card};
+use crate::rng;
+use crate::stats::{paired_difference, Summary};
+
+// A deck along with its kill turns on the shared seeds
+#[derive(Clone, Debug)]
+pub struct Evaluated {
+    pub deck: Vec<Card>,
+    pub kill_turns: Vec<f64>,
+}
+
+impl Evaluated {
+    pub fn summary(&self) -> Summary {
+        Summary::new(&self.kill_turns)
+    }
+}
+
+// Every legal deck we can make by taking out one card and putting in a card from the pool.
+// A swap is (out, in).
+pub fn swaps(deck: &[Card], pool: &[Card]) -> Vec<(Card, Card)> {
+    let mut answer = Vec::new();
+    for (i, out) in deck.iter().enumerate() {
+        // Only swap out the first copy of each card
+        if deck[..i].contains(out) {
+            continue;
+        }
+        for card in pool {
+            let swap = (*out, *card);
+            if out != card && !answer.contains(&swap) && check_deck(&apply_swap(deck, swap)).is_ok()
+            {
+                answer.push(swap);
+            }
+        }
+    }
+    answer
+}
+
+pub fn apply_swap(deck: &[Card], (out, card): (Card, Card)) -> Vec<Card> {
+    let mut answer = deck.to_vec();
+    let i = answer.iter().position(|c| *c == out).unwrap();
+    answer[i] = card;
+    answer
+}
+
+// The changes from one deck to another, like "-Evasion +Mailbox Dancer"
+pub fn deck_diff(from: &[Card], to: &[Card]) -> String {
+    let mut removed = from.to_vec();
+    let mut added = Vec::new();
+    for card in to {
+        match removed.iter().position(|c| c == card) {
+            Some(i) => {
+                removed.remove(i);
+            }
+            None => added.push(*card),
+        }
+    }
+    let mut parts: Vec<String> = removed.iter().map(|c| format!("-{}", c)).collect();
+    parts.extend(added.iter().map(|c| format!("+{}", c)));
+    if parts.is_empty() {
+        "no changes".to_string()
+    } else {
+        parts.join(" ")
+    }
+}
+
+// Hill climbs from the start deck, one swap at a time.
+// Each step evaluates a random sample of candidate swaps on the same seeds as the current
+// deck, so that comparisons are paired, and moves to the fastest candidate if it is
+// significantly faster. Stops after max_steps, or when no candidate is.
+// Returns every deck evaluated, starting with the start deck, and calls report with each
+// deck we move to.
+pub fn hill_climb(
+    start: &[Card],
+    pool: &[Card],
+    candidates: usize,
+    max_steps: usize,
+    evaluate: &mut dyn FnMut(&[Card]) -> Vec<f64>,
+    report: &mut dyn FnMut(&Evaluated),
+) -> Vec<Evaluated> {
+    let mut current = Evaluated {
+        deck: start.to_vec(),
+        kill_turns: evaluate(start),
+    };
+    report(&current);
+    let mut evaluated = vec![current.clone()];
+    for _ in 0..max_steps {
+        let mut options = swaps(&current.deck, pool);
+        rng::with(|rng| options.shuffle(rng));
+        options.truncate(candidates);
+
+        let mut best: Option<Evaluated> = None;
+        for swap in options {
+            let deck = apply_swap(&current.deck, swap);
+            let kill_turns = evaluate(&deck);
+            let candidate = Evaluated { deck, kill_turns };
+            if best
+                .as_ref()
+                .is_none_or(|b| candidate.summary().mean < b.summary().mean)
+            {
+                best = Some(candidate.clone());
+            }
+            evaluated.push(candidate);
+        }
+
+        // Lower kill turns are better, so the whole interval should be below zero
+        match best {
+            Some(b)
+                if paired_difference(&b.kill_turns, &current.kill_turns)
+                    .confidence_interval()
+                    .1
+                    < 0.0 =>
+            {
+                current = b;
+                report(&current);
+            }
+            _ => break,
+        }
+    }
+    evaluated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::card::PANDA_DECK;
+
+    #[test]
+    fn swaps_follow_the_rules() {
+        let pool: Vec<Card> = enum_iterator::all::<Card>().collect();
+        let all = swaps(PANDA_DECK, &pool);
+        assert!(all.contains(&(Card::Evasion, Card::Dancer)));
+        // Panda already has two of each non-legendary, and every legendary
+        assert!(!all.iter().any(|(_, card)| *card == Card::Coin));
+        assert!(!all.iter().any(|(_, card)| *card == Card::Foxy));
+        assert!(!all.iter().any(|(_, card)| *card == Card::Unknown));
+        for swap in all {
+            assert_eq!(check_deck(&apply_swap(PANDA_DECK, swap)), Ok(()));
+        }
+    }
+
+    #[test]
+    fn diffs() {
+        let deck = apply_swap(PANDA_DECK, (Card::Evasion, Card::Dancer));
+        assert_eq!(deck_diff(PANDA_DECK, &deck), "-Evasion +Mailbox Dancer");
+        assert_eq!(deck_diff(PANDA_DECK, PANDA_DECK), "no changes");
+    }
+
+    #[test]
+    fn climbing_removes_slow_cards() {
+        // Pretend every Evasion costs a turn, with the same noise across decks
+        let mut evaluate = |deck: &[Card]| -> Vec<f64> {
+            let evasions = deck.iter().filter(|c| **c == Card::Evasion).count();
+            (0..20).map(|i| (5 + i % 3 + evasions) as f64).collect()
+        };
+        let pool = [Card::Dancer, Card::BoneSpike];
+        let mut steps = 0;
+        rng::seed(0);
+        let evaluated = hill_climb(PANDA_DECK, &pool, 100, 10, &mut evaluate, &mut |_| {
+            steps += 1
+        });
+        // The start, then one step for each Evasion
+        assert_eq!(steps, 3);
+        let best = evaluated
+            .iter()
+            .min_by(|a, b| a.summary().mean.total_cmp(&b.summary().mean))
+            .unwrap();
+        assert!(!best.deck.contains(&Card::Evasion));
+    }
+}
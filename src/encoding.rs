@@ -0,0 +1,183 @@
+use enum_iterator::Sequence;
+
+use crate::card::{Card, CardInstance};
+use crate::game::{Action, Game, Play};
+
+// Bump this whenever the layout of the features or actions changes,
+// so that we never feed a model inputs it wasn't trained on.
+pub const ENCODER_VERSION: u32 = 1;
+
+pub const NUM_CARDS: usize = <Card as Sequence>::CARDINALITY;
+pub const HAND_SLOTS: usize = 10;
+pub const BOARD_SLOTS: usize = 7;
+pub const FISH_SLOTS: usize = 3;
+
+// Each hand slot is a one-hot card plus potion, tenwu, passage, cost reduction, and cost
+const HAND_SLOT_SIZE: usize = NUM_CARDS + 5;
+
+// mana, life, turn, storm, foxy, scabbs, next_scabbs, prep_pending,
+// hand size, deck size, and cards set aside with Secret Passage
+const NUM_SCALARS: usize = 11;
+
+// The layout is, in order:
+//   the hand, slot by slot
+//   the count of each card in hand
+//   the board, slot by slot, one-hot
+//   the count of each card in the deck
+//   the pending Gone Fishin' choices, slot by slot, one-hot
+//   the scalars
+pub const FEATURE_SIZE: usize = HAND_SLOTS * HAND_SLOT_SIZE
+    + NUM_CARDS
+    + BOARD_SLOTS * NUM_CARDS
+    + NUM_CARDS
+    + FISH_SLOTS * NUM_CARDS
+    + NUM_SCALARS;
+
+// Actions are laid out as end turn, then each fish choice, then each hand slot
+// with no target followed by each board target.
+pub const ACTION_SIZE: usize = 1 + FISH_SLOTS + HAND_SLOTS * (1 + BOARD_SLOTS);
+
+fn card_index(card: &Card) -> usize {
+    *card as usize
+}
+
+fn encode_slot(features: &mut Vec<f32>, card: Option<&Card>) {
+    let start = features.len();
+    features.resize(start + NUM_CARDS, 0.0);
+    if let Some(card) = card {
+        features[start + card_index(card)] = 1.0;
+    }
+}
+
+fn encode_hand_slot(features: &mut Vec<f32>, ci: Option<&CardInstance>) {
+    encode_slot(features, ci.map(|ci| &ci.card));
+    match ci {
+        Some(ci) => features.extend([
+            ci.potion as i32 as f32,
+            ci.tenwu as i32 as f32,
+            ci.passage as i32 as f32,
+            ci.cost_reduction as f32 / 10.0,
+            ci.cost() as f32 / 10.0,
+        ]),
+        None => features.extend([0.0; 5]),
+    }
+}
+
+fn encode_counts<'a>(features: &mut Vec<f32>, cards: impl Iterator<Item = &'a Card>, scale: f32) {
+    let start = features.len();
+    features.resize(start + NUM_CARDS, 0.0);
+    for card in cards {
+        features[start + card_index(card)] += 1.0 / scale;
+    }
+}
+
+// Encodes the game as a fixed-size vector of features, roughly scaled to [0, 1]
+pub fn encode_game(game: &Game) -> Vec<f32> {
+    let mut features = Vec::with_capacity(FEATURE_SIZE);
+
+    for i in 0..HAND_SLOTS {
+        encode_hand_slot(&mut features, game.hand.get(i));
+    }
+    encode_counts(&mut features, game.hand.iter().map(|ci| &ci.card), 2.0);
+    for i in 0..BOARD_SLOTS {
+        encode_slot(&mut features, game.board.get(i));
+    }
+    encode_counts(&mut features, game.deck.iter(), 2.0);
+    for i in 0..FISH_SLOTS {
+        encode_slot(&mut features, game.fish.get(i));
+    }
+
+    features.extend([
+        game.mana as f32 / 10.0,
+        game.life as f32 / 30.0,
+        game.turn as f32 / 10.0,
+        game.storm as f32 / 10.0,
+        game.foxy as f32 / 3.0,
+        game.scabbs as f32 / 3.0,
+        game.next_scabbs as f32 / 3.0,
+        game.prep_pending as i32 as f32,
+        game.hand.len() as f32 / HAND_SLOTS as f32,
+        game.deck.len() as f32 / 30.0,
+        game.passage.len() as f32 / HAND_SLOTS as f32,
+    ]);
+
+    assert_eq!(features.len(), FEATURE_SIZE);
+    features
+}
+
+// The index of an action in the fixed action space
+pub fn encode_action(action: &Action) -> usize {
+    match action {
+        Action::EndTurn => 0,
+        Action::Choose(i) => 1 + i,
+        Action::Play(play) => {
+            let slot = 1 + FISH_SLOTS + play.index * (1 + BOARD_SLOTS);
+            match play.target {
+                None => slot,
+                Some(t) => slot + 1 + t,
+            }
+        }
+    }
+}
+
+// The inverse of encode_action
+pub fn decode_action(index: usize) -> Action {
+    assert!(index < ACTION_SIZE);
+    if index == 0 {
+        return Action::EndTurn;
+    }
+    if index < 1 + FISH_SLOTS {
+        return Action::Choose(index - 1);
+    }
+    let offset = index - 1 - FISH_SLOTS;
+    let target = offset % (1 + BOARD_SLOTS);
+    Action::Play(Play {
+        index: offset / (1 + BOARD_SLOTS),
+        target: if target == 0 { None } else { Some(target - 1) },
+    })
+}
+
+// Which entries of the action space are legal among the provided actions
+pub fn action_mask(actions: &[Action]) -> Vec<bool> {
+    let mut mask = vec![false; ACTION_SIZE];
+    for action in actions {
+        mask[encode_action(action)] = true;
+    }
+    mask
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::card::PANDA_DECK;
+
+    #[test]
+    fn feature_size() {
+        assert_eq!(encode_game(&Game::new()).len(), FEATURE_SIZE);
+        let game = Game::new_going_second(PANDA_DECK);
+        assert_eq!(encode_game(&game).len(), FEATURE_SIZE);
+    }
+
+    #[test]
+    fn actions_round_trip() {
+        for index in 0..ACTION_SIZE {
+            assert_eq!(encode_action(&decode_action(index)), index);
+        }
+    }
+
+    #[test]
+    fn legal_actions_are_distinct() {
+        let mut game = Game::new();
+        game.mana = 10;
+        game.board = vec![Card::Foxy, Card::Scabbs, Card::Shark];
+        game.add_cards_to_hand(
+            vec![Card::Tenwu, Card::Shadowstep, Card::Pillager, Card::Coin].into_iter(),
+        );
+        let actions = game.actions();
+        let mask = action_mask(&actions);
+        assert_eq!(mask.iter().filter(|m| **m).count(), actions.len());
+        for action in actions {
+            assert_eq!(decode_action(encode_action(&action)), action);
+        }
+    }
+}
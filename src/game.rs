@@ -1,5 +1,5 @@
-use rand;
 use rand::seq::{IteratorRandom, SliceRandom};
+use rand::Rng;
 use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
 use std::fmt;
@@ -9,6 +9,8 @@ use std::time::Instant;
 
 use crate::card::Card;
 use crate::card::CardInstance;
+use crate::cardstats::{CardStats, Tally};
+use crate::rng;
 
 #[derive(Clone, Eq, Hash, PartialEq)]
 pub struct Game {
@@ -17,21 +19,22 @@ pub struct Game {
     pub passage: Vec<CardInstance>, // cards we've set aside with Secret Passage
     pub life: i32,                  // the opponent's life
     pub mana: i32,                  // our current mana
-    storm: i32,                     // number of things played this turn
-    foxy: i32,                      // number of stacks of the foxy effect
-    scabbs: i32,                    // number of stacks of the scabbs effect
-    next_scabbs: i32,               // number of stacks of scabbs effect after this one
+    pub(crate) storm: i32,          // number of things played this turn
+    pub(crate) foxy: i32,           // number of stacks of the foxy effect
+    pub(crate) scabbs: i32,         // number of stacks of the scabbs effect
+    pub(crate) next_scabbs: i32,    // number of stacks of scabbs effect after this one
     pub deck: Vec<Card>,            // the cards left in the deck
     pub turn: i32,                  // the current turn
-    prep_pending: bool,             // whether we have a preparation effect pending
+    pub(crate) prep_pending: bool,  // whether we have a preparation effect pending
     pub fish: Vec<Card>,            // the cards we can select for the pending Go Fishin'
+    pub(crate) tally: Tally,        // what happened to each card, if we're tracking that
 }
 
 // Representation of the different ways to play a card
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub struct Play {
-    pub index: usize,      // which card in hand to play
-    target: Option<usize>, // which card on the board to target, if any
+    pub index: usize,                 // which card in hand to play
+    pub(crate) target: Option<usize>, // which card on the board to target, if any
 }
 
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
@@ -49,12 +52,8 @@ pub enum Plan {
 }
 
 // Return a random index satisfying the predicate, or None if none does
-fn random_index_where<T>(v: &Vec<T>, f: impl Fn(&T) -> bool) -> Option<usize> {
-    let mut rng = rand::thread_rng();
-    match v.iter().enumerate().filter(|(_, x)| f(x)).choose(&mut rng) {
-        Some((i, _)) => Some(i),
-        None => None,
-    }
+fn random_index_where<T>(v: &[T], f: impl Fn(&T) -> bool) -> Option<usize> {
+    rng::with(|rng| v.iter().enumerate().filter(|(_, x)| f(x)).choose(rng)).map(|(i, _)| i)
 }
 
 impl fmt::Display for Game {
@@ -90,6 +89,12 @@ impl fmt::Display for Game {
     }
 }
 
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Game {
     pub fn new() -> Self {
         Self {
@@ -106,6 +111,7 @@ impl Game {
             turn: 0,
             prep_pending: false,
             fish: Vec::new(),
+            tally: Tally::default(),
         }
     }
 
@@ -134,6 +140,19 @@ impl Game {
         }
     }
 
+    // The first legal action that plays this card, or picks it for Go Fishin'.
+    // With a target, the play must target that card on the board.
+    pub fn action_for_card(&self, card: Card, target: Option<Card>) -> Option<Action> {
+        self.actions().into_iter().find(|action| match action {
+            Action::Play(play) => {
+                self.hand[play.index].card == card
+                    && target.is_none_or(|t| play.target.map(|i| self.board[i]) == Some(t))
+            }
+            Action::Choose(i) => self.fish[*i] == card && target.is_none(),
+            Action::EndTurn => false,
+        })
+    }
+
     pub fn can_combo(&self) -> bool {
         self.storm > 0
     }
@@ -164,8 +183,12 @@ impl Game {
 
     pub fn add_card_instances_to_hand(&mut self, iter: impl Iterator<Item = CardInstance>) {
         for ci in iter {
+            if !ci.from_deck {
+                self.tally.record(ci.card, false, |c| c.drawn += 1);
+            }
             if self.hand.len() >= 10 {
-                break;
+                self.tally.record(ci.card, ci.from_deck, |c| c.burned += 1);
+                continue;
             }
             self.hand.push(ci);
         }
@@ -183,6 +206,59 @@ impl Game {
         self.add_card_instance_to_hand(CardInstance::new(card))
     }
 
+    // Starts counting what happens to each card from here on.
+    // Cards missing from the deck we started with, like the opening hand, count as drawn,
+    // and generated cards already in hand, like The Coin, count as made.
+    // It's an error for the deck to hold more copies of a card than the starting deck did.
+    pub fn track_cards(&mut self, starting_deck: &[Card]) -> Result<(), String> {
+        let mut stats = CardStats::default();
+        for card in starting_deck {
+            stats.get_mut(*card).drawn += 1;
+        }
+        for card in &self.deck {
+            let counts = stats.get_mut(*card);
+            if counts.drawn == 0 {
+                return Err(format!(
+                    "the deck has more copies of {} than it started with",
+                    card
+                ));
+            }
+            counts.drawn -= 1;
+        }
+        for ci in self.hand.iter().filter(|ci| !ci.from_deck) {
+            stats.get_generated_mut(ci.card).drawn += 1;
+        }
+        self.tally = Tally(Some(Box::new(stats)));
+        Ok(())
+    }
+
+    pub fn card_stats(&self) -> Option<&CardStats> {
+        self.tally.0.as_deref()
+    }
+
+    // Runs f on this game with tracking off, so that copies made by searches don't pay for it
+    pub fn untracked<T>(&mut self, f: impl FnOnce(&Game) -> T) -> T {
+        let tally = std::mem::take(&mut self.tally);
+        let answer = f(self);
+        self.tally = tally;
+        answer
+    }
+
+    // Ends a tracked game by playing out the winning line, if there is one.
+    // Whatever is left in hand after that was stranded.
+    pub fn finish_tracking(&mut self, winning_line: &[Play]) {
+        for play in winning_line {
+            let ci = self.hand[play.index];
+            self.tally
+                .record(ci.card, ci.from_deck, |c| c.winning_line += 1);
+            self.play(play);
+        }
+        for ci in &self.hand {
+            self.tally
+                .record(ci.card, ci.from_deck, |c| c.stranded += 1);
+        }
+    }
+
     pub fn turn_is_fresh(&self) -> bool {
         self.storm == 0 && self.turn == self.mana
     }
@@ -193,23 +269,30 @@ impl Game {
             Card::Dancer => self.add_card_to_hand(&Card::Coin),
             Card::Foxy => self.foxy += 1,
             Card::Pillager => self.life -= self.storm,
-            Card::Scabbs => {
-                if self.storm > 0 {
-                    self.scabbs += 1;
-                    self.next_scabbs += 1;
-                }
+            Card::Scabbs if self.storm > 0 => {
+                self.scabbs += 1;
+                self.next_scabbs += 1;
             }
             _ => (),
         }
     }
 
+    // Puts a card we just took from the deck into our hand
+    fn draw_into_hand(&mut self, card: Card) {
+        self.tally.record(card, true, |c| c.drawn += 1);
+        self.add_card_instance_to_hand(CardInstance {
+            from_deck: true,
+            ..CardInstance::new(&card)
+        });
+    }
+
     // Draws a random card obeying the given predicate
     // Returns whether we succeeded
     fn draw_from(&mut self, pred: impl Fn(&Card) -> bool) -> bool {
         match random_index_where(&self.deck, |c| pred(c)) {
             Some(i) => {
                 let card = self.deck.remove(i);
-                self.add_card_to_hand(&card);
+                self.draw_into_hand(card);
                 true
             }
             None => false,
@@ -219,10 +302,10 @@ impl Game {
     // Draws the first card obeying the given predicate
     // Returns whether we succeeded
     fn draw_first(&mut self, pred: impl Fn(&Card) -> bool) -> bool {
-        match self.deck.iter().position(|c| pred(c)) {
+        match self.deck.iter().position(pred) {
             Some(i) => {
                 let card = self.deck.remove(i);
-                self.add_card_to_hand(&card);
+                self.draw_into_hand(card);
                 true
             }
             None => false,
@@ -273,7 +356,7 @@ impl Game {
     }
 
     pub fn new_going_random(deck: &[Card]) -> Self {
-        if rand::random() {
+        if rng::with(|rng| rng.gen()) {
             Self::new_going_first(deck)
         } else {
             Self::new_going_second(deck)
@@ -318,6 +401,8 @@ impl Game {
         self.mana -= self.cost(play.index);
         assert!(self.mana >= 0);
         self.hand.remove(play.index);
+        self.tally
+            .record(card.card, card.from_deck, |c| c.played += 1);
         self.scabbs = self.next_scabbs;
         self.next_scabbs = 0;
 
@@ -390,21 +475,18 @@ impl Game {
             }
             Card::Cutlass => {
                 self.draw();
-                match random_index_where::<CardInstance>(&self.hand, |c| {
+                if let Some(i) = random_index_where::<CardInstance>(&self.hand, |c| {
                     c.card.spell() && c.cost() > 0
                 }) {
-                    Some(i) => {
-                        self.hand[i].cost_reduction += 1;
-                    }
-                    None => (),
+                    self.hand[i].cost_reduction += 1;
                 }
             }
             Card::GoneFishin => {
                 if self.deck.len() <= 3 {
                     self.fish = self.deck.clone();
                 } else {
-                    let mut rng = &mut rand::thread_rng();
-                    self.fish = self.deck.choose_multiple(&mut rng, 3).cloned().collect();
+                    self.fish =
+                        rng::with(|rng| self.deck.choose_multiple(rng, 3).cloned().collect());
                 }
             }
             Card::SecretPassage => {
@@ -476,7 +558,7 @@ impl Game {
     pub fn actions(&self) -> Vec<Action> {
         if !self.fish.is_empty() {
             // Return a move for each index in fish
-            return (0..self.fish.len()).map(|i| Action::Choose(i)).collect();
+            return (0..self.fish.len()).map(Action::Choose).collect();
         }
         let mut answer = vec![Action::EndTurn];
         for (index, ci) in self.hand.iter().enumerate() {
@@ -569,11 +651,10 @@ impl Game {
     // We update cache as we go.
     fn find_deterministic_win_helper(
         &self,
-        start: Instant,
-        time_limit: f64,
+        budget: &mut SolverBudget,
         cache: &mut HashMap<u64, Plan>,
     ) -> Plan {
-        if start.elapsed().as_secs() as f64 > time_limit {
+        if !budget.spend() {
             return Plan::Timeout;
         }
         if self.is_win() {
@@ -589,7 +670,7 @@ impl Game {
         for play in self.deterministic_plays() {
             let mut clone = self.clone();
             clone.play(&play);
-            match clone.find_deterministic_win_helper(start, time_limit, cache) {
+            match clone.find_deterministic_win_helper(budget, cache) {
                 Plan::Win(mut plays) => {
                     plays.push(play);
                     let plan = Plan::Win(plays);
@@ -608,9 +689,18 @@ impl Game {
 
     // Returns a plan with list of moves to win.
     pub fn find_deterministic_win(&self, time_limit: f64) -> Plan {
-        let start = Instant::now();
+        self.find_deterministic_win_within(SolverLimit::Seconds(time_limit))
+    }
+
+    // Like find_deterministic_win, with either a time or a position limit
+    pub fn find_deterministic_win_within(&self, limit: SolverLimit) -> Plan {
+        let mut budget = SolverBudget {
+            limit,
+            start: Instant::now(),
+            nodes: 0,
+        };
         let mut cache = HashMap::new();
-        match self.find_deterministic_win_helper(start, time_limit, &mut cache) {
+        match self.find_deterministic_win_helper(&mut budget, &mut cache) {
             Plan::Win(mut plays) => {
                 plays.reverse();
                 Plan::Win(plays)
@@ -642,6 +732,114 @@ impl Game {
             }
         }
     }
+
+    // Returns the lowest life we can reach, with the reversed moves that reach it.
+    // Returns None if we run out of budget.
+    fn find_max_damage_helper(
+        &self,
+        budget: &mut SolverBudget,
+        cache: &mut HashMap<u64, (i32, Vec<Play>)>,
+    ) -> Option<(i32, Vec<Play>)> {
+        if !budget.spend() {
+            return None;
+        }
+        let hash = self.hash_value();
+        if let Some(best) = cache.get(&hash) {
+            return Some(best.clone());
+        }
+
+        let mut best = (self.life, Vec::new());
+        for play in self.deterministic_plays() {
+            let mut clone = self.clone();
+            clone.play(&play);
+            let (life, mut plays) = clone.find_max_damage_helper(budget, cache)?;
+            if life < best.0 {
+                plays.push(play);
+                best = (life, plays);
+            }
+        }
+        cache.insert(hash, best.clone());
+        Some(best)
+    }
+
+    // The most damage we can deal this turn, with the moves that deal it.
+    // Like find_deterministic_win, this only considers deterministic plays.
+    // Returns None if we run out of time.
+    pub fn find_max_damage(&self, time_limit: f64) -> Option<(i32, Vec<Play>)> {
+        self.find_max_damage_within(SolverLimit::Seconds(time_limit))
+    }
+
+    // Like find_max_damage, with either a time or a position limit
+    pub fn find_max_damage_within(&self, limit: SolverLimit) -> Option<(i32, Vec<Play>)> {
+        // Give the opponent enough life that the search never stops at lethal
+        let mut game = self.clone();
+        game.life = UNKILLABLE_LIFE;
+        let mut budget = SolverBudget {
+            limit,
+            start: Instant::now(),
+            nodes: 0,
+        };
+        let mut cache = HashMap::new();
+        let (life, mut plays) = game.find_max_damage_helper(&mut budget, &mut cache)?;
+        plays.reverse();
+        Some((UNKILLABLE_LIFE - life, plays))
+    }
+
+    // The most opponent life we can kill through this turn, whatever their life is now,
+    // with a line that does it.
+    // This is just the most damage we can deal, which relies on no card caring what the
+    // opponent's life is. We replay the line at that life to check.
+    pub fn max_lethal_life(&self, limit: SolverLimit) -> Result<(i32, Vec<Play>), LethalLifeError> {
+        let (damage, plays) = self
+            .find_max_damage_within(limit)
+            .ok_or(LethalLifeError::Timeout)?;
+        let mut witness = self.clone();
+        witness.life = damage;
+        for play in &plays {
+            witness.play(play);
+        }
+        if damage > 0 && !witness.is_win() {
+            return Err(LethalLifeError::WitnessFailed { damage });
+        }
+        Ok((damage, plays))
+    }
+}
+
+// Why max_lethal_life has no answer
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LethalLifeError {
+    // The search ran past its limit
+    Timeout,
+    // The line that deals this much damage doesn't kill an opponent with this much life
+    WitnessFailed { damage: i32 },
+}
+
+const UNKILLABLE_LIFE: i32 = 1_000_000;
+
+// How much searching the solver may do before it gives up.
+// A limit on nodes, the positions it searches, gives the same answer however fast the
+// machine is and whatever else it's doing, while a limit on seconds doesn't.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SolverLimit {
+    Seconds(f64),
+    Nodes(u64),
+}
+
+struct SolverBudget {
+    limit: SolverLimit,
+    start: Instant,
+    nodes: u64,
+}
+
+impl SolverBudget {
+    // Counts one more node, returning whether we're still within the limit
+    fn spend(&mut self) -> bool {
+        self.nodes += 1;
+        match self.limit {
+            SolverLimit::Seconds(seconds) => self.start.elapsed().as_secs_f64() <= seconds,
+            SolverLimit::Nodes(nodes) => self.nodes <= nodes,
+        }
+    }
 }
 
 // Expects that a win can be found with these parameters but not one more life
@@ -668,11 +866,23 @@ pub fn assert_exact_win_with_deck(mana: i32, life: i32, hand: Vec<Card>, deck: V
     }
 }
 
+// Like assert_exact_win_with_deck, and also expects max_lethal_life to find the same life
+pub fn assert_exact_max_lethal_life(mana: i32, life: i32, hand: Vec<Card>, deck: Vec<Card>) {
+    assert_exact_win_with_deck(mana, life, hand.clone(), deck.clone());
+    let mut game = Game::new();
+    game.mana = mana;
+    game.add_cards_to_hand(hand.into_iter());
+    game.deck = deck;
+    let (max_life, _) = game.max_lethal_life(SolverLimit::Nodes(1_000_000)).unwrap();
+    assert_eq!(max_life, life);
+}
+
 pub fn assert_exact_win(mana: i32, life: i32, hand: Vec<Card>) {
     assert_exact_win_with_deck(mana, life, hand, Vec::new());
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_comparison, clippy::len_zero)]
 mod tests {
     use super::*;
 
@@ -682,6 +892,41 @@ mod tests {
         assert!(game.hand.len() == 0)
     }
 
+    #[test]
+    fn tracking_deck_and_generated_cards() {
+        let deck = crate::card::PANDA_DECK;
+        let mut game = Game::new_going_second(deck);
+        game.track_cards(deck).unwrap();
+        let stats = game.card_stats().unwrap();
+        assert_eq!(stats.get_generated(Card::Coin).drawn, 1);
+        assert_eq!(stats.get(Card::Coin).drawn, 0);
+        let drawn: u32 = enum_iterator::all::<Card>()
+            .map(|card| stats.get(card).drawn)
+            .sum();
+        assert_eq!(drawn as usize, deck.len() - game.deck.len());
+
+        game.deck.push(Card::Coin);
+        assert!(game.track_cards(deck).is_err());
+    }
+
+    #[test]
+    fn actions_by_card() {
+        let mut g = Game::new();
+        g.mana = 10;
+        g.board = vec![Card::Dancer, Card::Pillager];
+        g.add_cards_to_hand(vec![Card::Dancer, Card::Shadowstep].into_iter());
+        assert_eq!(
+            g.action_for_card(Card::Shadowstep, Some(Card::Pillager)),
+            Some(Action::Play(Play {
+                index: 1,
+                target: Some(1)
+            }))
+        );
+        assert!(g.action_for_card(Card::Shadowstep, None).is_some());
+        assert_eq!(g.action_for_card(Card::Dancer, Some(Card::Pillager)), None);
+        assert_eq!(g.action_for_card(Card::Foxy, None), None);
+    }
+
     #[test]
     fn making_a_dancer() {
         let c: Card = Card::Dancer;
@@ -745,6 +990,67 @@ mod tests {
         assert!(g.life <= 0);
     }
 
+    #[test]
+    fn max_damage() {
+        let mut g = Game::new();
+        g.mana = 3;
+        g.add_cards_to_hand(
+            vec![
+                Card::Coin,
+                Card::Foxy,
+                Card::Shadowstep,
+                Card::Scabbs,
+                Card::Shark,
+                Card::Tenwu,
+                Card::Pillager,
+                Card::Pillager,
+            ]
+            .into_iter(),
+        );
+        let (damage, plays) = g.find_max_damage(10.0).unwrap();
+        // Matches t3_kill
+        assert_eq!(damage, 34);
+        let mut dying = g.clone();
+        dying.life = 1;
+        assert_eq!(
+            dying
+                .max_lethal_life(SolverLimit::Nodes(1_000_000))
+                .unwrap()
+                .0,
+            34
+        );
+        for play in plays {
+            g.play(&play);
+        }
+        assert_eq!(g.life, 30 - 34);
+    }
+
+    #[test]
+    fn max_lethal_life_limits() {
+        // Dealing out of a deck, like using_shroud
+        assert_exact_max_lethal_life(
+            5,
+            50,
+            vec![
+                Card::Coin,
+                Card::Shroud,
+                Card::Shadowstep,
+                Card::Scabbs,
+                Card::Dancer,
+                Card::Tenwu,
+                Card::Pillager,
+            ],
+            vec![Card::Shark, Card::Pillager, Card::Coin],
+        );
+        let mut g = Game::new();
+        g.mana = 4;
+        g.add_cards_to_hand([Card::Foxy, Card::Pillager].into_iter());
+        assert_eq!(
+            g.max_lethal_life(SolverLimit::Nodes(1)),
+            Err(LethalLifeError::Timeout)
+        );
+    }
+
     // Keep these tests sorted by mana, then life
 
     #[test]
@@ -1007,4 +1313,30 @@ mod tests {
             ],
         )
     }
+
+    #[test]
+    fn node_limits() {
+        let mut game = Game::new();
+        game.mana = 4;
+        game.add_cards_to_hand(
+            vec![
+                Card::Foxy,
+                Card::Shadowstep,
+                Card::Scabbs,
+                Card::Shark,
+                Card::Tenwu,
+                Card::Pillager,
+                Card::Pillager,
+            ]
+            .into_iter(),
+        );
+        assert_matches!(
+            game.find_deterministic_win_within(SolverLimit::Nodes(1)),
+            Plan::Timeout
+        );
+        assert_matches!(
+            game.find_deterministic_win_within(SolverLimit::Nodes(1_000_000)),
+            Plan::Win(_)
+        );
+    }
 }
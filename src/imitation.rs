@@ -0,0 +1,125 @@
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::card::Card;
+use crate::encoding::{encode_action, encode_game};
+use crate::game::{Action, Game, Plan};
+use crate::mcts::{Reward, MAX_TURNS};
+use crate::player::escape_bot_action;
+use crate::rng;
+use crate::selfplay::Sample;
+
+// The move we want the network to imitate in this position, as a policy target over the
+// actions MCTS considers, and whether the solver found lethal here.
+// Like MCTS, we only ask the solver at the start of a turn.
+fn label(game: &Game, solver_time: f64) -> (Vec<(usize, f32)>, bool) {
+    if !game.fish.is_empty() {
+        // The escape bot picks fish at random, so there's nothing to imitate
+        let p = 1.0 / game.fish.len() as f32;
+        let policy = (0..game.fish.len())
+            .map(|i| (encode_action(&Action::Choose(i)), p))
+            .collect();
+        return (policy, false);
+    }
+    if game.turn_is_fresh() {
+        if let Plan::Win(plays) = game.find_deterministic_win(solver_time) {
+            // MCTS never considers minions or Shadowstep, so a line starting with one of
+            // those gets the escape bot's move instead
+            let action = plays
+                .first()
+                .map(|play| Action::Play(*play))
+                .filter(|action| game.non_kill_actions().contains(action))
+                .unwrap_or_else(|| escape_bot_action(game));
+            return (vec![(encode_action(&action), 1.0)], true);
+        }
+    }
+    (vec![(encode_action(&escape_bot_action(game)), 1.0)], false)
+}
+
+// Plays one game, mostly following the escape bot but sometimes acting at random so that
+// we see a wider variety of positions. Each position is labeled with the solver's first
+// move if it finds lethal, and with the escape bot's move otherwise.
+// The game ends as a win once the solver finds lethal, as a search would treat it.
+pub fn label_game(deck: &[Card], explore: f64, solver_time: f64, reward: Reward) -> Vec<Sample> {
+    let mut game = Game::new_going_random(deck);
+    let mut samples = Vec::new();
+
+    let won = loop {
+        let (policy, lethal) = label(&game, solver_time);
+        samples.push(Sample {
+            features: encode_game(&game),
+            policy,
+            reward: 0.0,
+            kill_turn: 0,
+        });
+        if lethal {
+            break true;
+        }
+
+        let action = if rng::with(|rng| rng.gen_bool(explore)) {
+            let actions = game.non_kill_actions();
+            *rng::with(|rng| actions.choose(rng)).unwrap()
+        } else {
+            escape_bot_action(&game)
+        };
+        game.take_action(&action);
+        if game.turn >= MAX_TURNS {
+            break false;
+        }
+    };
+
+    let kill_turn = game.turn.min(MAX_TURNS);
+    for sample in &mut samples {
+        sample.reward = reward.value(kill_turn as f32, won);
+        sample.kill_turn = kill_turn;
+    }
+    samples
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::card::PANDA_DECK;
+    use crate::encoding::decode_action;
+
+    #[test]
+    fn labels_are_distributions() {
+        for _ in 0..5 {
+            let samples = label_game(PANDA_DECK, 0.2, 0.1, Reward::MeanTurn);
+            assert!(!samples.is_empty());
+            for sample in samples {
+                let total: f32 = sample.policy.iter().map(|(_, p)| p).sum();
+                assert!((total - 1.0).abs() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn lethal_is_labeled_with_the_solver() {
+        let mut game = Game::new();
+        game.turn = 4;
+        game.mana = 4;
+        game.add_cards_to_hand(
+            vec![
+                Card::Foxy,
+                Card::Shadowstep,
+                Card::Scabbs,
+                Card::Shark,
+                Card::Tenwu,
+                Card::Pillager,
+                Card::Pillager,
+            ]
+            .into_iter(),
+        );
+        let (policy, lethal) = label(&game, 1.0);
+        assert!(lethal);
+        assert_eq!(policy.len(), 1);
+        assert!(game
+            .non_kill_actions()
+            .contains(&decode_action(policy[0].0)));
+
+        // Partway through a turn we leave lethal to the search
+        game.mana = 3;
+        assert!(!label(&game, 1.0).1);
+    }
+}
@@ -1,8 +1,24 @@
 #[macro_use]
 extern crate assert_matches;
 
+pub mod agent;
 pub mod card;
+pub mod cardstats;
+pub mod cli;
+pub mod compare;
+pub mod deckopt;
+pub mod encoding;
 pub mod game;
+pub mod imitation;
 pub mod mcts;
+#[cfg(feature = "mlp")]
+pub mod mlp;
+#[cfg(feature = "torch")]
 pub mod model;
+pub mod openers;
 pub mod player;
+pub mod position;
+pub mod rng;
+pub mod selfplay;
+pub mod sim;
+pub mod stats;
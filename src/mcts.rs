@@ -1,17 +1,53 @@
+use std::borrow::Borrow;
+use std::cmp::Reverse;
 use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::iter::zip;
+use std::mem::size_of;
+use std::rc::Rc;
 
 use rand::seq::IteratorRandom;
 
 use crate::{
-    game::{Action, Game, Plan},
+    card::{Card, CardInstance},
+    game::{Action, Game, Plan, SolverLimit},
     player::escape_bot_action,
+    rng,
 };
 
-// A policy gives a distribution among possible actions for a given game state
-type Policy = fn(&Game, &Vec<Action>) -> Vec<f32>;
+// A policy gives a distribution among possible actions for a given game state.
+// Policies can carry state, like a loaded network, a config, or a cache.
+pub trait Policy {
+    fn evaluate(&mut self, game: &Game, actions: &[Action]) -> Vec<f32>;
 
-#[derive(Clone, Debug)]
+    // Evaluates several states at once.
+    // Policies that are faster in batches, like neural networks, should override this.
+    fn evaluate_batch(&mut self, batch: &[(&Game, &[Action])]) -> Vec<Vec<f32>> {
+        batch
+            .iter()
+            .map(|(game, actions)| self.evaluate(game, actions))
+            .collect()
+    }
+}
+
+// Plain functions like random_policy and escape_policy are stateless policies
+impl<F: Fn(&Game, &[Action]) -> Vec<f32>> Policy for F {
+    fn evaluate(&mut self, game: &Game, actions: &[Action]) -> Vec<f32> {
+        self(game, actions)
+    }
+}
+
+// A value estimator guesses the reward of a game state without playing it out
+pub trait ValueEstimator {
+    fn estimate(&mut self, game: &Game) -> f32;
+
+    fn estimate_batch(&mut self, games: &[&Game]) -> Vec<f32> {
+        games.iter().map(|game| self.estimate(game)).collect()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 struct StateActionData {
     action: Action,
 
@@ -23,23 +59,130 @@ struct StateActionData {
     // Also known as Q(s, a)
     reward: f32,
 
+    // Average squared reward, so that we can tell how noisy the reward is
+    reward_sq: f32,
+
     // Number of times this (state, action) pair has been visited
     // Also known as N(s, a)
     visits: u32,
+
+    // Playouts in the current batch that went through this pair but haven't finished.
+    // Each counts as a visit with zero reward, to steer the rest of the batch elsewhere.
+    virtual_loss: u32,
+}
+
+// How confident the lower confidence bound should be, in standard deviations
+const LOWER_BOUND_Z: f32 = 1.96;
+
+impl StateActionData {
+    // A lower confidence bound on Q(s, a).
+    // We need at least two playouts to estimate the variance.
+    fn lower_bound(&self) -> f32 {
+        if self.visits < 2 {
+            return f32::NEG_INFINITY;
+        }
+        let n = self.visits as f32;
+        let variance = (self.reward_sq - self.reward * self.reward).max(0.0) * n / (n - 1.0);
+        self.reward - LOWER_BOUND_Z * (variance / n).sqrt()
+    }
+
+    fn stats(&self) -> ActionStats {
+        ActionStats {
+            action: self.action,
+            visits: self.visits,
+            reward: self.reward,
+            prior: self.shallow,
+            lower_bound: self.lower_bound(),
+        }
+    }
+}
+
+// Search statistics for one action from a state, for display and for picking a final move
+#[derive(Clone, Copy, Debug)]
+pub struct ActionStats {
+    pub action: Action,
+    pub visits: u32,      // N(s, a)
+    pub reward: f32,      // Q(s, a)
+    pub prior: f32,       // P(s, a)
+    pub lower_bound: f32, // a lower confidence bound on Q(s, a)
+}
+
+// How to pick the move to actually play once the search is done
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FinalMove {
+    MaxReward,  // the highest Q(s, a), which can be fooled by one lucky playout
+    MaxVisits,  // the most visited action
+    LowerBound, // the highest lower confidence bound on Q(s, a)
+    RobustMax,  // the most visited action if it also has the highest Q(s, a), else LowerBound
+}
+
+impl FinalMove {
+    // Returns the index of the chosen action.
+    // Ties on the main criterion are broken by the other statistics, then by the prior.
+    pub fn choose(&self, stats: &[ActionStats]) -> usize {
+        let by_visits = |a: &ActionStats, b: &ActionStats| {
+            a.visits
+                .cmp(&b.visits)
+                .then(a.reward.total_cmp(&b.reward))
+                .then(a.prior.total_cmp(&b.prior))
+        };
+        let by_reward = |a: &ActionStats, b: &ActionStats| {
+            a.reward
+                .total_cmp(&b.reward)
+                .then(a.visits.cmp(&b.visits))
+                .then(a.prior.total_cmp(&b.prior))
+        };
+        let by_lower_bound = |a: &ActionStats, b: &ActionStats| {
+            a.lower_bound
+                .total_cmp(&b.lower_bound)
+                .then(by_visits(a, b))
+        };
+        let best = |cmp: &dyn Fn(&ActionStats, &ActionStats) -> std::cmp::Ordering| {
+            stats
+                .iter()
+                .enumerate()
+                .max_by(|(_, a), (_, b)| cmp(a, b))
+                .unwrap()
+                .0
+        };
+        match self {
+            FinalMove::MaxReward => best(&by_reward),
+            FinalMove::MaxVisits => best(&by_visits),
+            FinalMove::LowerBound => best(&by_lower_bound),
+            FinalMove::RobustMax => {
+                let i = best(&by_visits);
+                if i == best(&by_reward) {
+                    i
+                } else {
+                    best(&by_lower_bound)
+                }
+            }
+        }
+    }
 }
 
 // Information relevant to a game state during the MCTS playout
 // The vectors are parallel to non_kill_candidate_moves
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 struct StateData {
     deterministic_win: bool,
     actions: Vec<StateActionData>,
+
+    // The last playout generation that touched this state, for eviction
+    generation: u64,
+
+    // The order this state was first stored in, which breaks ties when evicting
+    inserted: u64,
 }
 
 impl StateData {
-    fn new(game: &Game, policy: Policy) -> StateData {
+    fn new(game: &Game, policy: &mut dyn Policy) -> StateData {
         let actions = game.non_kill_actions();
-        let shallow = policy(&game, &actions);
+        let shallow = policy.evaluate(game, &actions);
+        StateData::from_priors(actions, shallow)
+    }
+
+    fn from_priors(actions: Vec<Action>, shallow: Vec<f32>) -> StateData {
         StateData {
             deterministic_win: false,
             actions: zip(actions, shallow)
@@ -47,9 +190,13 @@ impl StateData {
                     action,
                     shallow,
                     reward: 0.0,
+                    reward_sq: 0.0,
                     visits: 0,
+                    virtual_loss: 0,
                 })
                 .collect(),
+            generation: 0,
+            inserted: 0,
         }
     }
 
@@ -57,12 +204,22 @@ impl StateData {
         StateData {
             deterministic_win: true,
             actions: Vec::new(),
+            generation: 0,
+            inserted: 0,
         }
     }
 
+    fn total_visits(&self) -> u32 {
+        self.actions.iter().map(|a| a.visits).sum()
+    }
+
     // Pick the index with the highest upper confidence bound
     fn explore_index(&self) -> usize {
-        let total_visits = self.actions.iter().map(|a| a.visits).sum::<u32>() as f32;
+        let total_visits = self
+            .actions
+            .iter()
+            .map(|a| a.visits + a.virtual_loss)
+            .sum::<u32>() as f32;
 
         // Give each candidate an upper confidence bound on the expected value of the reward
         // Also known as U(s, a).
@@ -70,11 +227,19 @@ impl StateData {
         //   https://web.stanford.edu/~surag/posts/alphazero.html
         // We add the 0.01 so that we get something reasonable when the Q(s, a) are all zero
         let exploration_parameter = 1.0;
-        let numerator = (0.01 + total_visits as f32).sqrt() * exploration_parameter;
+        let numerator = (0.01 + total_visits).sqrt() * exploration_parameter;
         let upper_bounds: Vec<f32> = self
             .actions
             .iter()
-            .map(|a| a.reward + numerator * a.shallow / (1.0 + a.visits as f32))
+            .map(|a| {
+                let visits = (a.visits + a.virtual_loss) as f32;
+                let reward = if a.virtual_loss == 0 {
+                    a.reward
+                } else {
+                    a.reward * a.visits as f32 / visits
+                };
+                reward + numerator * a.shallow / (1.0 + visits)
+            })
             .collect();
 
         // Pick the candidate with the highest upper confidence bound
@@ -87,107 +252,613 @@ impl StateData {
     }
 
     fn update(&mut self, index: usize, reward: f32) {
-        let mut action = &mut self.actions[index];
-        action.reward =
-            (action.reward * action.visits as f32 + reward) / (action.visits as f32 + 1.0);
+        let action = &mut self.actions[index];
+        let n = action.visits as f32;
+        action.reward = (action.reward * n + reward) / (n + 1.0);
+        action.reward_sq = (action.reward_sq * n + reward * reward) / (n + 1.0);
         action.visits += 1;
     }
 
-    // Pick the best reward, ignoring confidence
-    fn best_action(&self) -> Action {
-        self.actions
-            .iter()
-            .max_by(|a, b| a.reward.total_cmp(&b.reward))
-            .unwrap()
-            .action
+    fn stats(&self) -> Vec<ActionStats> {
+        self.actions.iter().map(|a| a.stats()).collect()
     }
 }
 
+// How states are keyed in the state map.
+// Full keys keep a clone of the game, including its deck and hand, which the paths of
+// batched playouts share rather than cloning it again.
+// Hashed keys keep only the 64-bit hash, which is much smaller but can collide.
+#[derive(Clone)]
+enum StateKey {
+    Full(Rc<Game>),
+    Hashed(u64),
+}
+
+// A state key that borrows the game, so that looking up a state doesn't clone it
+#[derive(Clone, Copy, Eq, Hash, PartialEq)]
+enum KeyRef<'a> {
+    Full(&'a Game),
+    Hashed(u64),
+}
+
+// Both kinds of key, so that the state map can look up owned keys with borrowed ones.
+// They hash and compare through their KeyRef, which keeps the two consistent.
+trait AsKeyRef {
+    fn key_ref(&self) -> KeyRef<'_>;
+}
+
+impl AsKeyRef for StateKey {
+    fn key_ref(&self) -> KeyRef<'_> {
+        match self {
+            StateKey::Full(game) => KeyRef::Full(game),
+            StateKey::Hashed(hash) => KeyRef::Hashed(*hash),
+        }
+    }
+}
+
+impl AsKeyRef for KeyRef<'_> {
+    fn key_ref(&self) -> KeyRef<'_> {
+        *self
+    }
+}
+
+impl<'a> Borrow<dyn AsKeyRef + 'a> for StateKey {
+    fn borrow(&self) -> &(dyn AsKeyRef + 'a) {
+        self
+    }
+}
+
+impl Hash for dyn AsKeyRef + '_ {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key_ref().hash(state);
+    }
+}
+
+impl PartialEq for dyn AsKeyRef + '_ {
+    fn eq(&self, other: &Self) -> bool {
+        self.key_ref() == other.key_ref()
+    }
+}
+
+impl Eq for dyn AsKeyRef + '_ {}
+
+impl Hash for StateKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key_ref().hash(state);
+    }
+}
+
+impl PartialEq for StateKey {
+    fn eq(&self, other: &StateKey) -> bool {
+        self.key_ref() == other.key_ref()
+    }
+}
+
+impl Eq for StateKey {}
+
+// Which states to drop when the state map grows past its cap
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Eviction {
+    LeastVisited, // drop the states with the fewest visits first
+    Oldest,       // drop the states that haven't been touched for the longest
+}
+
 pub struct MCTS {
     // Data for state-action pairs
-    state_map: HashMap<Game, StateData>,
+    state_map: HashMap<StateKey, StateData>,
+
+    policy: Box<dyn Policy>,
+
+    // Whether to key states by hash rather than by a full clone of the game
+    hashed_keys: bool,
+
+    // The most states to keep in the state map, if capped
+    max_nodes: Option<usize>,
+
+    eviction: Eviction,
+
+    final_move: FinalMove,
+
+    reward: Reward,
+
+    // If set, we estimate the reward of new states instead of playing them out
+    evaluator: Option<Box<dyn ValueEstimator>>,
+
+    // How many playouts search runs at once, evaluating their new states together
+    batch_size: usize,
+
+    // How hard to look for a deterministic win at the start of each new turn
+    solver: SolverLimit,
 
-    policy: Policy,
+    // Incremented on each playout, so we know how recently a state was used
+    generation: u64,
+
+    // How many states we have stored so far
+    insertions: u64,
 }
 
+pub const MAX_TURNS: i32 = 10;
+
+// How many positions the solver may search at each new turn, by default, which takes
+// about 0.05 seconds on a typical core.
+// Counting positions rather than seconds keeps searches reproducible.
+const DEFAULT_SOLVER_NODES: u64 = 50_000;
+
+// What the search is trying to optimize.
 // Design the reward to be nonnegative so that it looks better than branches we haven't tried
-const MAX_TURNS: i32 = 10;
-fn reward(game: &Game) -> f32 {
-    (MAX_TURNS - game.turn) as f32
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Reward {
+    MeanTurn,        // MAX_TURNS minus the kill turn, which minimizes the mean kill turn
+    WinByTurn(i32),  // 1 for killing by the given turn, which maximizes P(win by turn N)
+    Discounted(f32), // discount^turn for killing, so earlier kills count for more
+}
+
+impl Reward {
+    // The reward for a game that ended on this turn.
+    // The turn can be fractional when it is an estimate.
+    pub fn value(&self, turn: f32, won: bool) -> f32 {
+        match self {
+            Reward::MeanTurn => MAX_TURNS as f32 - turn.min(MAX_TURNS as f32),
+            Reward::WinByTurn(n) => {
+                if won && turn <= *n as f32 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            Reward::Discounted(discount) => {
+                if won {
+                    discount.powf(turn)
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+
+    // Parses names like "mean-turn", "win-by-6", or "discounted-0.8"
+    pub fn from_name(s: &str) -> Option<Reward> {
+        if s == "mean-turn" {
+            return Some(Reward::MeanTurn);
+        }
+        if let Some(n) = s.strip_prefix("win-by-") {
+            return n.parse().ok().map(Reward::WinByTurn);
+        }
+        if let Some(d) = s.strip_prefix("discounted-") {
+            return d.parse().ok().map(Reward::Discounted);
+        }
+        None
+    }
+}
+
+impl fmt::Display for Reward {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Reward::MeanTurn => write!(f, "mean-turn"),
+            Reward::WinByTurn(n) => write!(f, "win-by-{}", n),
+            Reward::Discounted(d) => write!(f, "discounted-{}", d),
+        }
+    }
+}
+
+// What a playout finds when it reaches a state
+enum Visit {
+    Done(f32), // the playout is over, with this reward
+    New,       // a state we haven't expanded yet
+    Known(StateData),
+}
+
+// Where a playout in a batch stopped while the new states are waiting to be evaluated
+enum Leaf {
+    Done(f32),
+    Pending(Game),
+}
+
+// When we evict, we go this far under the cap, so that we don't evict on every playout
+const EVICTION_FRACTION: f32 = 0.9;
+
+// Approximate number of heap bytes owned by a game
+fn game_heap_bytes(game: &Game) -> usize {
+    game.board.capacity() * size_of::<Card>()
+        + game.hand.capacity() * size_of::<CardInstance>()
+        + game.passage.capacity() * size_of::<CardInstance>()
+        + game.deck.capacity() * size_of::<Card>()
+        + game.fish.capacity() * size_of::<Card>()
 }
 
 impl MCTS {
-    pub fn new(policy: Policy) -> MCTS {
+    pub fn new(policy: impl Policy + 'static) -> MCTS {
         MCTS {
             state_map: HashMap::new(),
-            policy,
+            policy: Box::new(policy),
+            hashed_keys: false,
+            max_nodes: None,
+            eviction: Eviction::LeastVisited,
+            final_move: FinalMove::MaxVisits,
+            reward: Reward::MeanTurn,
+            evaluator: None,
+            batch_size: 1,
+            solver: SolverLimit::Nodes(DEFAULT_SOLVER_NODES),
+            generation: 0,
+            insertions: 0,
+        }
+    }
+
+    pub fn with_reward(mut self, reward: Reward) -> MCTS {
+        self.reward = reward;
+        self
+    }
+
+    // Stops each playout at the first new state, using the evaluator's estimate as the reward
+    pub fn with_evaluator(mut self, evaluator: impl ValueEstimator + 'static) -> MCTS {
+        self.evaluator = Some(Box::new(evaluator));
+        self
+    }
+
+    // Makes search run playouts in batches, which is much faster with a neural network
+    pub fn with_batch_size(mut self, batch_size: usize) -> MCTS {
+        assert!(batch_size > 0);
+        self.batch_size = batch_size;
+        self
+    }
+
+    // Limits the solver by time, which makes searches depend on the machine and its load
+    pub fn with_solver_time(mut self, solver_time: f64) -> MCTS {
+        self.solver = SolverLimit::Seconds(solver_time);
+        self
+    }
+
+    pub fn with_solver_nodes(mut self, solver_nodes: u64) -> MCTS {
+        self.solver = SolverLimit::Nodes(solver_nodes);
+        self
+    }
+
+    pub fn with_final_move(mut self, final_move: FinalMove) -> MCTS {
+        self.final_move = final_move;
+        self
+    }
+
+    // Caps the number of states we keep, evicting according to the provided policy
+    pub fn with_max_nodes(mut self, max_nodes: usize, eviction: Eviction) -> MCTS {
+        self.max_nodes = Some(max_nodes);
+        self.eviction = eviction;
+        self
+    }
+
+    // Keys states by their hash instead of storing a full clone of each game
+    pub fn with_hashed_keys(mut self) -> MCTS {
+        self.hashed_keys = true;
+        self.state_map.clear();
+        self
+    }
+
+    // An owned key, for storing a new state
+    fn key(&self, game: &Game) -> StateKey {
+        if self.hashed_keys {
+            StateKey::Hashed(game.hash_value())
+        } else {
+            StateKey::Full(Rc::new(game.clone()))
+        }
+    }
+
+    // The key the state map stores for a state that's in it, which is cheap to clone
+    fn stored_key(&self, game: &Game) -> StateKey {
+        let key = self.key_ref(game);
+        let (stored, _) = self
+            .state_map
+            .get_key_value(&key as &dyn AsKeyRef)
+            .expect("the state is stored");
+        stored.clone()
+    }
+
+    // A borrowed key, for looking up a state
+    fn key_ref<'a>(&self, game: &'a Game) -> KeyRef<'a> {
+        if self.hashed_keys {
+            KeyRef::Hashed(game.hash_value())
+        } else {
+            KeyRef::Full(game)
+        }
+    }
+
+    fn get(&self, game: &Game) -> Option<&StateData> {
+        self.state_map.get(&self.key_ref(game) as &dyn AsKeyRef)
+    }
+
+    fn get_mut(&mut self, game: &Game) -> Option<&mut StateData> {
+        let key = self.key_ref(game);
+        self.state_map.get_mut(&key as &dyn AsKeyRef)
+    }
+
+    // Stores the data for a state, only making an owned key if the state is new
+    fn insert(&mut self, game: &Game, mut state_data: StateData) {
+        if let Some(existing) = self.get_mut(game) {
+            state_data.inserted = existing.inserted;
+            *existing = state_data;
+        } else {
+            state_data.inserted = self.insertions;
+            self.insertions += 1;
+            let key = self.key(game);
+            self.state_map.insert(key, state_data);
+        }
+    }
+
+    // The number of states in the state map
+    pub fn node_count(&self) -> usize {
+        self.state_map.len()
+    }
+
+    // A rough estimate of how much memory the state map uses
+    pub fn approximate_bytes(&self) -> usize {
+        // Each hash map slot holds a key, a value, and a control byte
+        let slots = self.state_map.capacity() * (size_of::<(StateKey, StateData)>() + 1);
+        let heap: usize = self
+            .state_map
+            .iter()
+            .map(|(key, data)| {
+                let key_bytes = match key {
+                    // The game, and the reference counts next to it
+                    StateKey::Full(game) => {
+                        size_of::<Game>() + 2 * size_of::<usize>() + game_heap_bytes(game)
+                    }
+                    StateKey::Hashed(_) => 0,
+                };
+                key_bytes + data.actions.capacity() * size_of::<StateActionData>()
+            })
+            .sum();
+        slots + heap
+    }
+
+    // Drops states until we are comfortably under the cap
+    fn evict(&mut self) {
+        let max_nodes = match self.max_nodes {
+            Some(n) => n,
+            None => return,
+        };
+        if self.state_map.len() <= max_nodes {
+            return;
         }
+        let target = (max_nodes as f32 * EVICTION_FRACTION) as usize;
+        if target == 0 {
+            self.state_map.clear();
+            return;
+        }
+
+        // Higher ranks are kept. No two states were inserted at the same time, so the ranks
+        // are all different, and we keep exactly the target number of states.
+        let eviction = self.eviction;
+        let rank = move |data: &StateData| {
+            let visits = data.total_visits() as u64;
+            match eviction {
+                Eviction::LeastVisited => (visits, data.generation, data.inserted),
+                Eviction::Oldest => (data.generation, visits, data.inserted),
+            }
+        };
+        let mut ranks: Vec<(u64, u64, u64)> = self.state_map.values().map(rank).collect();
+        let (_, cutoff, _) = ranks.select_nth_unstable_by_key(target - 1, |r| Reverse(*r));
+        let cutoff = *cutoff;
+        self.state_map.retain(|_, data| rank(data) >= cutoff);
     }
 
     // Does a playout from the provided game state
     // Returns the reward for the playout.
     pub fn playout(&mut self, game: &Game) -> f32 {
+        self.generation += 1;
+        let answer = self.playout_helper(game);
+        self.evict();
+        answer
+    }
+
+    // Looks up a state at the start of a playout step, ending the playout if the game is over
+    fn visit(&mut self, game: &Game) -> Visit {
         if game.turn >= MAX_TURNS {
-            return reward(game);
+            return Visit::Done(self.reward.value(game.turn as f32, false));
         }
 
-        let state_data = self.state_map.get(&game);
-        if state_data.is_none() && game.turn_is_fresh() {
-            // Check for a deterministic win
-            if let Plan::Win(_) = game.find_deterministic_win(0.05) {
-                let answer = reward(game);
-                self.state_map.insert(game.clone(), StateData::new_win());
-                return answer;
+        match self.get(game) {
+            // We already have found that this is a deterministic win
+            Some(s) if s.deterministic_win => {
+                Visit::Done(self.reward.value(game.turn as f32, true))
             }
-        }
-        if let Some(state_data) = state_data {
-            if state_data.deterministic_win {
-                // We already have found that this is a deterministic win
-                return reward(game);
+            Some(s) => Visit::Known(s.clone()),
+            None => {
+                if game.turn_is_fresh() {
+                    // Check for a deterministic win
+                    if let Plan::Win(_) = game.find_deterministic_win_within(self.solver) {
+                        let answer = self.reward.value(game.turn as f32, true);
+                        let mut win = StateData::new_win();
+                        win.generation = self.generation;
+                        self.insert(game, win);
+                        return Visit::Done(answer);
+                    }
+                }
+                Visit::New
             }
         }
+    }
 
-        let mut state_data = match state_data {
-            Some(s) => s.clone(),
-            None => StateData::new(game, self.policy),
+    fn playout_helper(&mut self, game: &Game) -> f32 {
+        let state_data = match self.visit(game) {
+            Visit::Done(answer) => return answer,
+            Visit::Known(state_data) => state_data,
+            Visit::New => {
+                let mut state_data = StateData::new(game, self.policy.as_mut());
+                if let Some(evaluator) = &mut self.evaluator {
+                    // Expand this state, but estimate its value rather than going deeper
+                    let answer = evaluator.estimate(game);
+                    state_data.generation = self.generation;
+                    self.insert(game, state_data);
+                    return answer;
+                }
+                state_data
+            }
         };
+        self.continue_playout(game, state_data)
+    }
 
+    // Takes the most promising action from an expanded state, plays out the rest of the game,
+    // and updates the state with the result
+    fn continue_playout(&mut self, game: &Game, mut state_data: StateData) -> f32 {
         // Choose a move
         let i = state_data.explore_index();
         let mut game_clone = game.clone();
         game_clone.take_action(&state_data.actions[i].action);
 
         // Recurse
-        let answer = self.playout(&game_clone);
+        let answer = self.playout_helper(&game_clone);
 
         // Update with the results of the playout
         state_data.update(i, answer);
-        if let Some(s) = self.state_map.get_mut(game) {
-            *s = state_data;
+        state_data.generation = self.generation;
+        self.insert(game, state_data);
+
+        answer
+    }
+
+    // Does a batch of playouts from the provided game state, evaluating the new state each one
+    // reaches in a single call to the policy and the evaluator.
+    // Returns the reward for each playout.
+    pub fn playout_batch(&mut self, game: &Game, size: usize) -> Vec<f32> {
+        self.generation += 1;
+
+        // Walk down the tree, adding virtual loss so that the rest of the batch spreads out
+        let mut paths = Vec::with_capacity(size);
+        let mut leaves = Vec::with_capacity(size);
+        for _ in 0..size {
+            let mut game = game.clone();
+            let mut path = Vec::new();
+            let leaf = loop {
+                match self.visit(&game) {
+                    Visit::Done(answer) => break Leaf::Done(answer),
+                    Visit::New => break Leaf::Pending(game),
+                    Visit::Known(mut state_data) => {
+                        // The path outlives this game, so it shares the stored key
+                        let i = state_data.explore_index();
+                        state_data.actions[i].virtual_loss += 1;
+                        let action = state_data.actions[i].action;
+                        self.insert(&game, state_data);
+                        path.push((self.stored_key(&game), i));
+                        game.take_action(&action);
+                    }
+                }
+            };
+            paths.push(path);
+            leaves.push(leaf);
+        }
+
+        // Evaluate all the new states at once
+        let pending: Vec<(&Game, Vec<Action>)> = leaves
+            .iter()
+            .filter_map(|leaf| match leaf {
+                Leaf::Pending(game) => Some((game, game.non_kill_actions())),
+                Leaf::Done(_) => None,
+            })
+            .collect();
+        let batch: Vec<(&Game, &[Action])> = pending
+            .iter()
+            .map(|(game, actions)| (*game, actions.as_slice()))
+            .collect();
+        let mut priors = if batch.is_empty() {
+            Vec::new()
         } else {
-            self.state_map.insert(game.clone(), state_data);
+            self.policy.evaluate_batch(&batch)
+        };
+        let mut values = match &mut self.evaluator {
+            Some(evaluator) if !batch.is_empty() => {
+                let games: Vec<&Game> = batch.iter().map(|(game, _)| *game).collect();
+                Some(evaluator.estimate_batch(&games))
+            }
+            _ => None,
+        };
+        let mut pending_actions: Vec<Vec<Action>> =
+            pending.into_iter().map(|(_, actions)| actions).collect();
+
+        // Finish each playout in order, then back up its reward
+        let mut answers = Vec::with_capacity(size);
+        let mut j = 0;
+        for (path, leaf) in zip(paths, leaves) {
+            let answer = match leaf {
+                Leaf::Done(answer) => answer,
+                Leaf::Pending(game) => {
+                    let actions = std::mem::take(&mut pending_actions[j]);
+                    let prior = std::mem::take(&mut priors[j]);
+                    let value = values.as_mut().map(|values| values[j]);
+                    j += 1;
+
+                    // Two playouts in the batch can reach the same new state
+                    let mut state_data = match self.get(&game) {
+                        Some(s) => s.clone(),
+                        None => StateData::from_priors(actions, prior),
+                    };
+                    match value {
+                        Some(value) => {
+                            state_data.generation = self.generation;
+                            self.insert(&game, state_data);
+                            value
+                        }
+                        None => self.continue_playout(&game, state_data),
+                    }
+                }
+            };
+            for (key, i) in path.into_iter().rev() {
+                if let Some(state_data) = self.state_map.get_mut(&key) {
+                    state_data.actions[i].virtual_loss -= 1;
+                    state_data.update(i, answer);
+                    state_data.generation = self.generation;
+                }
+            }
+            answers.push(answer);
         }
 
-        answer
+        self.evict();
+        answers
     }
 
-    // Returns the best action.
-    // If we have no idea, just pick the first one.
-    pub fn best_action(&self, game: &Game) -> Action {
-        match self.state_map.get(game) {
-            Some(s) => s.best_action(),
-            None => game.non_kill_actions()[0],
+    // Runs this many playouts from the provided game state, in batches if we have a batch size
+    pub fn search(&mut self, game: &Game, playouts: usize) {
+        if self.batch_size == 1 {
+            for _ in 0..playouts {
+                self.playout(game);
+            }
+            return;
+        }
+        // Expand the root on its own first, so that the first batch doesn't spend every
+        // playout finding the same new state
+        let mut remaining = playouts;
+        if remaining > 0 && self.get(game).is_none() {
+            self.playout(game);
+            remaining -= 1;
+        }
+        while remaining > 0 {
+            let size = remaining.min(self.batch_size);
+            self.playout_batch(game, size);
+            remaining -= size;
         }
     }
+
+    // Returns the statistics for each non-kill action from this state.
+    // States we haven't searched get zero visits and just their prior.
+    pub fn root_stats(&mut self, game: &Game) -> Vec<ActionStats> {
+        match self.get(game) {
+            Some(s) if !s.actions.is_empty() => s.stats(),
+            _ => StateData::new(game, self.policy.as_mut()).stats(),
+        }
+    }
+
+    // Returns the best action according to our final move rule.
+    // If we have no idea, we trust the policy.
+    pub fn best_action(&mut self, game: &Game) -> Action {
+        let stats = self.root_stats(game);
+        stats[self.final_move.choose(&stats)].action
+    }
 }
 
-pub fn random_policy(_: &Game, actions: &Vec<Action>) -> Vec<f32> {
+pub fn random_policy(_: &Game, actions: &[Action]) -> Vec<f32> {
     (0..actions.len())
         .map(|_| 1.0 / actions.len() as f32)
         .collect()
 }
 
-pub fn escape_policy(game: &Game, actions: &Vec<Action>) -> Vec<f32> {
+pub fn escape_policy(game: &Game, actions: &[Action]) -> Vec<f32> {
     let action = escape_bot_action(game);
     // Find m in actions
     match actions.iter().position(|a| a == &action) {
@@ -204,13 +875,72 @@ pub fn escape_policy(game: &Game, actions: &Vec<Action>) -> Vec<f32> {
     }
 }
 
+// Estimates the reward from the combo pieces in hand, the mana we will have,
+// and how long it should take to draw the missing pieces from the deck.
+pub struct HeuristicEvaluator {
+    reward: Reward,
+}
+
+// Groups of interchangeable combo pieces, with how many turns it costs to be missing one
+// if it isn't in the deck at all.
+const COMBO_PIECES: &[(&[Card], f32)] = &[
+    (&[Card::Pillager], 4.0),
+    (&[Card::Foxy, Card::Scabbs], 2.0),
+    (&[Card::Shark], 1.0),
+    (&[Card::Tenwu, Card::Shadowstep, Card::Potion], 1.0),
+];
+
+// The mana we usually need to assemble a kill
+const COMBO_MANA: i32 = 5;
+
+// This deck draws a lot, so we see more than one card per turn
+const DRAWS_PER_TURN: f32 = 2.0;
+
+impl HeuristicEvaluator {
+    pub fn new(reward: Reward) -> HeuristicEvaluator {
+        HeuristicEvaluator { reward }
+    }
+
+    pub fn estimate_kill_turn(game: &Game) -> f32 {
+        // Pieces that are gone each cost their penalty, while we wait to draw the others
+        // at the same time, so only the longest wait counts
+        let mut penalties: f32 = 0.0;
+        let mut longest_wait: f32 = 0.0;
+        for (cards, penalty) in COMBO_PIECES {
+            if game.hand.iter().any(|ci| cards.contains(&ci.card)) {
+                continue;
+            }
+            let copies = game.deck.iter().filter(|c| cards.contains(c)).count();
+            if copies == 0 {
+                penalties += penalty;
+                continue;
+            }
+
+            // The expected position of the first copy in a shuffled deck
+            let draws = (game.deck.len() + 1) as f32 / (copies + 1) as f32;
+            longest_wait = longest_wait.max(draws / DRAWS_PER_TURN);
+        }
+        let turns_to_draw = penalties + longest_wait;
+
+        // This doesn't count a kill this turn. The deterministic search looks for one at the
+        // start of each turn, and in the middle of a turn we estimate as if it doesn't come.
+        let mana_turn = std::cmp::max(game.turn + 1, COMBO_MANA) as f32;
+        let kill_turn = mana_turn.max(game.turn as f32 + 1.0 + turns_to_draw);
+        kill_turn.min(MAX_TURNS as f32)
+    }
+}
+
+impl ValueEstimator for HeuristicEvaluator {
+    fn estimate(&mut self, game: &Game) -> f32 {
+        let turn = HeuristicEvaluator::estimate_kill_turn(game);
+        self.reward.value(turn, turn < MAX_TURNS as f32)
+    }
+}
+
 pub fn random_action(game: &Game) -> Action {
     // Select a random element
-    *game
-        .non_kill_actions()
-        .iter()
-        .choose(&mut rand::thread_rng())
-        .unwrap()
+    let actions = game.non_kill_actions();
+    *rng::with(|rng| actions.iter().choose(rng)).unwrap()
 }
 
 pub fn mcts_action(game: &Game) -> Action {
@@ -220,3 +950,247 @@ pub fn mcts_action(game: &Game) -> Action {
     }
     mcts.best_action(game)
 }
+
+// Like mcts_action, but estimates new states with the heuristic rather than playing them out
+pub fn heuristic_mcts_action(game: &Game) -> Action {
+    let mut mcts =
+        MCTS::new(escape_policy).with_evaluator(HeuristicEvaluator::new(Reward::MeanTurn));
+    for _ in 0..200 {
+        mcts.playout(game);
+    }
+    mcts.best_action(game)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::card::PANDA_DECK;
+
+    #[test]
+    fn max_nodes_is_respected() {
+        let game = Game::new_going_first(PANDA_DECK);
+        let mut mcts = MCTS::new(escape_policy).with_max_nodes(50, Eviction::LeastVisited);
+        for _ in 0..100 {
+            mcts.playout(&game);
+            assert!(mcts.node_count() <= 50);
+        }
+        // The root has the most visits, so it should survive eviction
+        assert!(mcts.get(&game).is_some());
+    }
+
+    #[test]
+    fn capped_searches_are_reproducible() {
+        // Each map hashes in its own random order, so ties must not depend on that order
+        let game = Game::new_going_first(PANDA_DECK);
+        let search = |eviction| {
+            rng::seed(5);
+            let mut mcts = MCTS::new(escape_policy)
+                .with_solver_nodes(1000)
+                .with_max_nodes(30, eviction);
+            mcts.search(&game, 100);
+            mcts.state_map
+        };
+        for eviction in [Eviction::LeastVisited, Eviction::Oldest] {
+            let first = search(eviction);
+            assert!(first.len() <= 30);
+            assert!(first == search(eviction));
+        }
+    }
+
+    #[test]
+    fn lower_bound_from_updates() {
+        let mut data = StateData::from_priors(vec![Action::EndTurn], vec![1.0]);
+        data.update(0, 1.0);
+        assert_eq!(data.actions[0].lower_bound(), f32::NEG_INFINITY);
+        for reward in [2.0, 3.0, 4.0] {
+            data.update(0, reward);
+        }
+        // The mean is 2.5 and the sample variance is 5/3, so the standard error is sqrt(5/12)
+        let expected = 2.5 - LOWER_BOUND_Z * (5.0f32 / 12.0).sqrt();
+        assert!((data.actions[0].lower_bound() - expected).abs() < 1e-4);
+        assert_eq!(data.stats()[0].lower_bound, data.actions[0].lower_bound());
+    }
+
+    fn stats(visits: u32, reward: f32, prior: f32, lower_bound: f32) -> ActionStats {
+        ActionStats {
+            action: Action::EndTurn,
+            visits,
+            reward,
+            prior,
+            lower_bound,
+        }
+    }
+
+    #[test]
+    fn final_move_rules() {
+        // A lucky single visit against a well explored action
+        let lucky = stats(1, 9.0, 0.1, f32::NEG_INFINITY);
+        let explored = stats(40, 6.0, 0.5, 5.5);
+        let noisy = stats(30, 6.5, 0.4, 4.0);
+        let all = [lucky, explored, noisy];
+        assert_eq!(FinalMove::MaxReward.choose(&all), 0);
+        assert_eq!(FinalMove::MaxVisits.choose(&all), 1);
+        assert_eq!(FinalMove::LowerBound.choose(&all), 1);
+        assert_eq!(FinalMove::RobustMax.choose(&all), 1);
+    }
+
+    #[test]
+    fn final_move_ties() {
+        // With no visits at all, we should fall back to the prior
+        let all = [stats(0, 0.0, 0.2, 0.0), stats(0, 0.0, 0.6, 0.0)];
+        assert_eq!(FinalMove::MaxVisits.choose(&all), 1);
+        assert_eq!(FinalMove::MaxReward.choose(&all), 1);
+
+        // Equal visits are broken by reward
+        let all = [stats(5, 3.0, 0.9, 0.0), stats(5, 4.0, 0.1, 0.0)];
+        assert_eq!(FinalMove::MaxVisits.choose(&all), 1);
+    }
+
+    #[test]
+    fn heuristic_prefers_combo_pieces() {
+        let mut empty = Game::new();
+        empty.turn = 3;
+        empty.deck = PANDA_DECK.to_vec();
+        let mut ready = empty.clone();
+        ready.add_cards_to_hand(
+            vec![Card::Pillager, Card::Foxy, Card::Shark, Card::Tenwu].into_iter(),
+        );
+        let mut evaluator = HeuristicEvaluator::new(Reward::MeanTurn);
+        assert!(evaluator.estimate(&ready) > evaluator.estimate(&empty));
+        assert_eq!(HeuristicEvaluator::estimate_kill_turn(&ready), 5.0);
+
+        // No Pillager left costs 4 turns on top of the 1 turn wait to draw the Shark
+        let mut missing = Game::new();
+        missing.turn = 3;
+        missing.deck = vec![Card::Shark, Card::Coin, Card::Coin];
+        missing.add_cards_to_hand(vec![Card::Foxy, Card::Tenwu].into_iter());
+        assert_eq!(HeuristicEvaluator::estimate_kill_turn(&missing), 9.0);
+    }
+
+    #[test]
+    fn evaluator_bounds_playouts() {
+        let game = Game::new_going_first(PANDA_DECK);
+        let mut mcts =
+            MCTS::new(escape_policy).with_evaluator(HeuristicEvaluator::new(Reward::MeanTurn));
+        for i in 0..20 {
+            mcts.playout(&game);
+            // Each playout expands at most one new state
+            assert!(mcts.node_count() <= i + 1);
+        }
+    }
+
+    #[test]
+    fn reward_names() {
+        for reward in [
+            Reward::MeanTurn,
+            Reward::WinByTurn(6),
+            Reward::Discounted(0.8),
+        ] {
+            assert_eq!(Reward::from_name(&reward.to_string()), Some(reward));
+        }
+        assert_eq!(Reward::from_name("win-by-"), None);
+    }
+
+    #[test]
+    fn reward_values() {
+        assert_eq!(Reward::MeanTurn.value(6.0, true), 4.0);
+        assert_eq!(Reward::MeanTurn.value(10.0, false), 0.0);
+        assert_eq!(Reward::WinByTurn(6).value(6.0, true), 1.0);
+        assert_eq!(Reward::WinByTurn(6).value(7.0, true), 0.0);
+        assert!(
+            Reward::Discounted(0.8).value(5.0, true) > Reward::Discounted(0.8).value(6.0, true)
+        );
+        assert_eq!(Reward::Discounted(0.8).value(10.0, false), 0.0);
+    }
+
+    fn batch_tree(batch_size: Option<usize>, evaluator: bool) -> HashMap<StateKey, StateData> {
+        rng::seed(7);
+        let game = Game::new_going_first(PANDA_DECK);
+        // Most of the time goes to the solver, so a small budget keeps the test quick
+        let mut mcts = MCTS::new(escape_policy).with_solver_nodes(1000);
+        if evaluator {
+            mcts = mcts.with_evaluator(HeuristicEvaluator::new(Reward::MeanTurn));
+        }
+        for _ in 0..100 {
+            match batch_size {
+                Some(size) => {
+                    mcts.playout_batch(&game, size);
+                }
+                None => {
+                    mcts.playout(&game);
+                }
+            }
+        }
+        mcts.state_map
+    }
+
+    #[test]
+    fn batch_of_one_matches_unbatched() {
+        for evaluator in [false, true] {
+            let unbatched = batch_tree(None, evaluator);
+            assert!(unbatched.len() > 1);
+            assert!(batch_tree(Some(1), evaluator) == unbatched);
+        }
+    }
+
+    #[test]
+    fn batches_clear_virtual_loss() {
+        let game = Game::new_going_first(PANDA_DECK);
+        for with_evaluator in [false, true] {
+            let mut mcts = MCTS::new(escape_policy).with_batch_size(8);
+            if with_evaluator {
+                mcts = mcts.with_evaluator(HeuristicEvaluator::new(Reward::MeanTurn));
+            }
+            mcts.search(&game, 50);
+            let root = mcts.get(&game).unwrap();
+            // With an evaluator, the playout that expands the root stops there.
+            // Every other playout passes through the root.
+            let expected = if with_evaluator { 50 - 1 } else { 50 };
+            assert_eq!(root.total_visits(), expected);
+            for state_data in mcts.state_map.values() {
+                assert!(state_data.actions.iter().all(|a| a.virtual_loss == 0));
+            }
+        }
+    }
+
+    #[test]
+    fn borrowed_keys_find_stored_states() {
+        let game = Game::new_going_first(PANDA_DECK);
+        let mut other = game.clone();
+        other.take_action(&Action::EndTurn);
+        for mut mcts in [
+            MCTS::new(escape_policy),
+            MCTS::new(escape_policy).with_hashed_keys(),
+        ] {
+            mcts.insert(&game, StateData::new_win());
+            assert!(mcts.get(&game).unwrap().deterministic_win);
+            assert!(mcts.get(&other).is_none());
+            mcts.insert(
+                &game,
+                StateData::from_priors(vec![Action::EndTurn], vec![1.0]),
+            );
+            assert_eq!(mcts.node_count(), 1);
+            assert!(mcts.state_map.contains_key(&mcts.key(&game)));
+            // A stored full key shares its game rather than cloning it
+            if let StateKey::Full(stored) = mcts.stored_key(&game) {
+                assert_eq!(Rc::strong_count(&stored), 2);
+            }
+        }
+    }
+
+    #[test]
+    fn hashed_keys_are_smaller() {
+        let game = Game::new_going_first(PANDA_DECK);
+        let mut full = MCTS::new(random_policy);
+        let mut hashed = MCTS::new(random_policy).with_hashed_keys();
+        for _ in 0..50 {
+            full.playout(&game);
+            hashed.playout(&game);
+        }
+        assert!(hashed.node_count() > 0);
+        assert!(
+            hashed.approximate_bytes() / hashed.node_count()
+                < full.approximate_bytes() / full.node_count()
+        );
+    }
+}
@@ -0,0 +1,345 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::encoding::{encode_action, encode_game, ACTION_SIZE, ENCODER_VERSION, FEATURE_SIZE};
+use crate::game::{Action, Game};
+use crate::mcts::{Policy, ValueEstimator};
+use crate::selfplay::{invalid, read_f32, read_u32, read_u8, write_f32, write_u32, WIN_TURNS};
+
+// A fully connected layer, with weights stored row by row as [outputs][inputs]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Layer {
+    pub name: String,
+    pub inputs: usize,
+    pub outputs: usize,
+    pub weights: Vec<f32>,
+    pub biases: Vec<f32>,
+}
+
+impl Layer {
+    pub fn forward(&self, xs: &[f32]) -> Vec<f32> {
+        assert_eq!(xs.len(), self.inputs);
+        self.weights
+            .chunks(self.inputs)
+            .zip(&self.biases)
+            .map(|(row, bias)| bias + row.iter().zip(xs).map(|(w, x)| w * x).sum::<f32>())
+            .collect()
+    }
+}
+
+// The same network as model::Network, evaluated in plain Rust so that it runs without libtorch.
+// The trunk layers use ReLU, and the policy and value heads read from the last trunk layer.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Mlp {
+    pub trunk: Vec<Layer>,
+    pub policy: Layer,
+    pub value: Layer,
+}
+
+// Files are little-endian binary:
+//   the magic bytes, then format version, encoder version, and layer count as u32s,
+//   then each layer as its name length as a u8, the name, inputs and outputs as u32s,
+//   the weights as f32s, and the biases as f32s.
+// The heads are the layers with known names, like "policy", "value", and "win",
+// and the rest are the trunk in order.
+const MAGIC: &[u8; 4] = b"GFML";
+const FORMAT_VERSION: u32 = 1;
+
+// Checks that the trunk and heads fit together and match the encoding
+fn check_layers(trunk: &[Layer], heads: &[(&Layer, usize)]) -> io::Result<()> {
+    for layer in trunk.iter().chain(heads.iter().map(|(layer, _)| *layer)) {
+        if layer.weights.len() != layer.inputs * layer.outputs
+            || layer.biases.len() != layer.outputs
+        {
+            return Err(invalid(format!("layer {} has the wrong size", layer.name)));
+        }
+    }
+    let mut inputs = FEATURE_SIZE;
+    for layer in trunk {
+        if layer.inputs != inputs {
+            return Err(invalid(format!(
+                "layer {} has {} inputs but we expect {}",
+                layer.name, layer.inputs, inputs
+            )));
+        }
+        inputs = layer.outputs;
+    }
+    for (layer, outputs) in heads {
+        if layer.inputs != inputs || layer.outputs != *outputs {
+            return Err(invalid(format!(
+                "layer {} is {}x{} but we expect {}x{}",
+                layer.name, layer.inputs, layer.outputs, inputs, outputs
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn write_layers(path: impl AsRef<Path>, layers: &[&Layer]) -> io::Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    w.write_all(MAGIC)?;
+    for x in [FORMAT_VERSION, ENCODER_VERSION, layers.len() as u32] {
+        write_u32(&mut w, x)?;
+    }
+    for layer in layers {
+        w.write_all(&[layer.name.len() as u8])?;
+        w.write_all(layer.name.as_bytes())?;
+        write_u32(&mut w, layer.inputs as u32)?;
+        write_u32(&mut w, layer.outputs as u32)?;
+        for x in layer.weights.iter().chain(&layer.biases) {
+            write_f32(&mut w, *x)?;
+        }
+    }
+    w.flush()
+}
+
+// Reads the layers in a file, in order
+fn read_layers(path: impl AsRef<Path>) -> io::Result<Vec<Layer>> {
+    let mut r = BufReader::new(File::open(path)?);
+    let mut magic = [0; 4];
+    r.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid("not an exported network".to_string()));
+    }
+    for (name, value) in [
+        ("format version", FORMAT_VERSION),
+        ("encoder version", ENCODER_VERSION),
+    ] {
+        let found = read_u32(&mut r)?;
+        if found != value {
+            return Err(invalid(format!(
+                "network has {} {} but we expect {}",
+                name, found, value
+            )));
+        }
+    }
+
+    let count = read_u32(&mut r)?;
+    let mut layers = Vec::new();
+    for _ in 0..count {
+        let mut name = vec![0; read_u8(&mut r)? as usize];
+        r.read_exact(&mut name)?;
+        let name = String::from_utf8(name).map_err(|e| invalid(e.to_string()))?;
+        let inputs = read_u32(&mut r)? as usize;
+        let outputs = read_u32(&mut r)? as usize;
+        let mut read_floats = |n: usize| {
+            (0..n)
+                .map(|_| read_f32(&mut r))
+                .collect::<io::Result<Vec<f32>>>()
+        };
+        layers.push(Layer {
+            name,
+            inputs,
+            outputs,
+            weights: read_floats(inputs * outputs)?,
+            biases: read_floats(outputs)?,
+        });
+    }
+    Ok(layers)
+}
+
+// Removes the head with this name from the layers
+fn take_head(layers: &mut Vec<Layer>, name: &str) -> io::Result<Layer> {
+    match layers.iter().position(|layer| layer.name == name) {
+        Some(i) => Ok(layers.remove(i)),
+        None => Err(invalid(format!("network is missing a {} head", name))),
+    }
+}
+
+// Runs the features through the trunk, with a ReLU after each layer
+fn trunk_forward(trunk: &[Layer], features: &[f32]) -> Vec<f32> {
+    let mut hidden = features.to_vec();
+    for layer in trunk {
+        hidden = layer.forward(&hidden);
+        for x in &mut hidden {
+            *x = x.max(0.0);
+        }
+    }
+    hidden
+}
+
+impl Mlp {
+    pub fn new(trunk: Vec<Layer>, policy: Layer, value: Layer) -> io::Result<Mlp> {
+        check_layers(&trunk, &[(&policy, ACTION_SIZE), (&value, 1)])?;
+        Ok(Mlp {
+            trunk,
+            policy,
+            value,
+        })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let layers: Vec<&Layer> = self
+            .trunk
+            .iter()
+            .chain([&self.policy, &self.value])
+            .collect();
+        write_layers(path, &layers)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Mlp> {
+        let mut layers = read_layers(path)?;
+        let policy = take_head(&mut layers, "policy")?;
+        let value = take_head(&mut layers, "value")?;
+        Mlp::new(layers, policy, value)
+    }
+
+    // Returns the policy logits over the whole action space and the predicted value
+    pub fn forward(&self, features: &[f32]) -> (Vec<f32>, f32) {
+        let hidden = trunk_forward(&self.trunk, features);
+        (self.policy.forward(&hidden), self.value.forward(&hidden)[0])
+    }
+
+    // Returns the policy over the provided actions and the predicted value
+    pub fn predict(&self, game: &Game, actions: &[Action]) -> (Vec<f32>, f32) {
+        let (logits, value) = self.forward(&encode_game(game));
+        let logits: Vec<f32> = actions.iter().map(|a| logits[encode_action(a)]).collect();
+        let max = logits.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        let exps: Vec<f32> = logits.iter().map(|x| (x - max).exp()).collect();
+        let total: f32 = exps.iter().sum();
+        (exps.iter().map(|x| x / total).collect(), value)
+    }
+}
+
+// Like Rc<Model>, a shared Mlp can act as both the MCTS policy and its leaf evaluator
+impl Policy for Rc<Mlp> {
+    fn evaluate(&mut self, game: &Game, actions: &[Action]) -> Vec<f32> {
+        self.predict(game, actions).0
+    }
+}
+
+impl ValueEstimator for Rc<Mlp> {
+    fn estimate(&mut self, game: &Game) -> f32 {
+        self.predict(game, &game.non_kill_actions()).1
+    }
+}
+
+// Estimates P(kill by turn n) for each turn up to WIN_TURNS, from the same kind of trunk
+// as Mlp but with a single "win" head. These are trained on how self-play games ended.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WinMlp {
+    pub trunk: Vec<Layer>,
+    pub win: Layer,
+}
+
+impl WinMlp {
+    pub fn new(trunk: Vec<Layer>, win: Layer) -> io::Result<WinMlp> {
+        check_layers(&trunk, &[(&win, WIN_TURNS)])?;
+        Ok(WinMlp { trunk, win })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let layers: Vec<&Layer> = self.trunk.iter().chain([&self.win]).collect();
+        write_layers(path, &layers)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<WinMlp> {
+        let mut layers = read_layers(path)?;
+        let win = take_head(&mut layers, "win")?;
+        WinMlp::new(layers, win)
+    }
+
+    // The probability of having killed by turn i + 1, for each i
+    pub fn forward(&self, features: &[f32]) -> Vec<f32> {
+        let hidden = trunk_forward(&self.trunk, features);
+        self.win
+            .forward(&hidden)
+            .iter()
+            .map(|x| 1.0 / (1.0 + (-x).exp()))
+            .collect()
+    }
+
+    pub fn kill_chances(&self, game: &Game) -> Vec<f32> {
+        self.forward(&encode_game(game))
+    }
+
+    // Formats the chances for the turns still to come, like "turn 5: 12%, turn 6: 30%"
+    pub fn kill_chances_string(&self, game: &Game) -> String {
+        let chances = self.kill_chances(game);
+        let turns: Vec<String> = (1..=WIN_TURNS)
+            .filter(|turn| *turn as i32 >= game.turn)
+            .map(|turn| format!("turn {}: {:.0}%", turn, 100.0 * chances[turn - 1]))
+            .collect();
+        turns.join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::card::PANDA_DECK;
+    use crate::rng;
+    use rand::Rng;
+
+    fn layer(name: &str, inputs: usize, outputs: usize) -> Layer {
+        let random = |n: usize| rng::with(|rng| (0..n).map(|_| rng.gen_range(-0.1..0.1)).collect());
+        Layer {
+            name: name.to_string(),
+            inputs,
+            outputs,
+            weights: random(inputs * outputs),
+            biases: random(outputs),
+        }
+    }
+
+    fn random_mlp() -> Mlp {
+        Mlp::new(
+            vec![layer("fc1", FEATURE_SIZE, 16), layer("fc2", 16, 16)],
+            layer("policy", 16, ACTION_SIZE),
+            layer("value", 16, 1),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn save_and_load() {
+        let mlp = random_mlp();
+        let path = std::env::temp_dir().join(format!("goldfish-{}.mlp", std::process::id()));
+        mlp.save(&path).unwrap();
+        let loaded = Mlp::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, mlp);
+    }
+
+    #[test]
+    fn policy_is_a_distribution() {
+        let mut mlp = Rc::new(random_mlp());
+        let game = Game::new_going_first(PANDA_DECK);
+        let actions = game.non_kill_actions();
+        let policy = mlp.evaluate(&game, &actions);
+        assert_eq!(policy.len(), actions.len());
+        let total: f32 = policy.iter().sum();
+        assert!((total - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn win_chances() {
+        let win = WinMlp::new(
+            vec![layer("fc1", FEATURE_SIZE, 16)],
+            layer("win", 16, WIN_TURNS),
+        )
+        .unwrap();
+        let path = std::env::temp_dir().join(format!("goldfish-win-{}.mlp", std::process::id()));
+        win.save(&path).unwrap();
+        let loaded = WinMlp::load(&path).unwrap();
+        assert!(Mlp::load(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded, win);
+
+        let chances = win.kill_chances(&Game::new_going_first(PANDA_DECK));
+        assert_eq!(chances.len(), WIN_TURNS);
+        assert!(chances.iter().all(|p| *p > 0.0 && *p < 1.0));
+    }
+
+    #[test]
+    fn shapes_are_checked() {
+        let result = Mlp::new(
+            vec![layer("fc1", FEATURE_SIZE, 16)],
+            layer("policy", 8, ACTION_SIZE),
+            layer("value", 16, 1),
+        );
+        assert!(result.is_err());
+    }
+}
@@ -1,15 +1,697 @@
-use tch::Cuda;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use rand::seq::SliceRandom;
+use tch::nn::{self, Module};
+use tch::{Cuda, Device, Kind, Reduction, TchError, Tensor};
+
+use crate::encoding::{
+    action_mask, encode_action, encode_game, ACTION_SIZE, ENCODER_VERSION, FEATURE_SIZE,
+};
+use crate::game::{Action, Game};
+use crate::mcts::{Policy, ValueEstimator};
+#[cfg(feature = "mlp")]
+use crate::mlp::{Layer, Mlp, WinMlp};
+use crate::rng;
+use crate::selfplay::{Sample, WIN_TURNS};
 
 pub fn cuda_available() -> bool {
     Cuda::is_available()
 }
 
+// Run on the GPU when we have one, and on the CPU otherwise
+pub fn default_device() -> Device {
+    Device::cuda_if_available()
+}
+
+// Encodes a batch of games as a [batch, FEATURE_SIZE] float tensor
+pub fn games_tensor(games: &[&Game], device: Device) -> Tensor {
+    let features: Vec<f32> = games.iter().flat_map(|game| encode_game(game)).collect();
+    Tensor::of_slice(&features)
+        .view([games.len() as i64, FEATURE_SIZE as i64])
+        .to_device(device)
+}
+
+// Encodes which actions are legal as a [batch, ACTION_SIZE] bool tensor
+pub fn mask_tensor(actions: &[&[Action]], device: Device) -> Tensor {
+    let mask: Vec<bool> = actions.iter().flat_map(|a| action_mask(a)).collect();
+    Tensor::of_slice(&mask)
+        .view([actions.len() as i64, ACTION_SIZE as i64])
+        .to_kind(Kind::Bool)
+        .to_device(device)
+}
+
+const HIDDEN_SIZE: i64 = 256;
+
+// A small MLP with a shared trunk, a policy head over the whole action space,
+// and a value head that predicts the reward.
+#[derive(Debug)]
+pub struct Network {
+    fc1: nn::Linear,
+    fc2: nn::Linear,
+    policy: nn::Linear,
+    value: nn::Linear,
+}
+
+impl Network {
+    pub fn new(vs: &nn::Path) -> Network {
+        let config = Default::default();
+        Network {
+            fc1: nn::linear(vs / "fc1", FEATURE_SIZE as i64, HIDDEN_SIZE, config),
+            fc2: nn::linear(vs / "fc2", HIDDEN_SIZE, HIDDEN_SIZE, config),
+            policy: nn::linear(vs / "policy", HIDDEN_SIZE, ACTION_SIZE as i64, config),
+            value: nn::linear(vs / "value", HIDDEN_SIZE, 1, config),
+        }
+    }
+
+    // Returns policy logits with shape [batch, ACTION_SIZE] and values with shape [batch]
+    pub fn forward(&self, xs: &Tensor) -> (Tensor, Tensor) {
+        let hidden = self.fc2.forward(&self.fc1.forward(xs).relu()).relu();
+        let logits = self.policy.forward(&hidden);
+        let values = self.value.forward(&hidden).squeeze_dim(-1);
+        (logits, values)
+    }
+}
+
+// The network along with the variables it was built from
+pub struct Model {
+    pub vs: nn::VarStore,
+    pub network: Network,
+}
+
+impl Model {
+    pub fn new(device: Device) -> Model {
+        let vs = nn::VarStore::new(device);
+        let network = Network::new(&vs.root());
+        Model { vs, network }
+    }
+
+    pub fn load(path: impl AsRef<Path>, device: Device) -> Result<Model, TchError> {
+        let mut model = Model::new(device);
+        model.vs.load(path)?;
+        Ok(model)
+    }
+
+    pub fn device(&self) -> Device {
+        self.vs.device()
+    }
+
+    // Returns, for each game, the policy over the provided actions and the predicted value
+    pub fn predict(&self, games: &[&Game], actions: &[&[Action]]) -> (Vec<Vec<f32>>, Vec<f32>) {
+        let (probs, values) = tch::no_grad(|| {
+            let xs = games_tensor(games, self.device());
+            let mask = mask_tensor(actions, self.device());
+            let (logits, values) = self.network.forward(&xs);
+            let probs = logits
+                .masked_fill(&mask.logical_not(), f64::NEG_INFINITY)
+                .softmax(-1, Kind::Float);
+            (probs.to_device(Device::Cpu), values.to_device(Device::Cpu))
+        });
+        let probs: Vec<Vec<f32>> = Vec::from(&probs);
+        let policies = probs
+            .iter()
+            .zip(actions)
+            .map(|(p, actions)| actions.iter().map(|a| p[encode_action(a)]).collect())
+            .collect();
+        (policies, Vec::from(&values))
+    }
+}
+
+// Copies a layer's weights out of libtorch
+#[cfg(feature = "mlp")]
+fn export_layer(name: &str, linear: &nn::Linear) -> Layer {
+    let size = linear.ws.size();
+    let weights = linear.ws.to_device(Device::Cpu).flatten(0, -1);
+    let biases = match &linear.bs {
+        Some(bs) => Vec::from(&bs.to_device(Device::Cpu)),
+        None => vec![0.0; size[0] as usize],
+    };
+    Layer {
+        name: name.to_string(),
+        inputs: size[1] as usize,
+        outputs: size[0] as usize,
+        weights: Vec::from(&weights),
+        biases,
+    }
+}
+
+#[cfg(feature = "mlp")]
+impl Model {
+    // Converts to the dependency-free format, for running without libtorch
+    pub fn to_mlp(&self) -> Mlp {
+        let network = &self.network;
+        Mlp::new(
+            vec![
+                export_layer("fc1", &network.fc1),
+                export_layer("fc2", &network.fc2),
+            ],
+            export_layer("policy", &network.policy),
+            export_layer("value", &network.value),
+        )
+        .unwrap()
+    }
+}
+
+// Stacks the training targets for a batch of samples.
+// Returns the features, the policy targets, and the value targets.
+pub fn samples_tensors(samples: &[&Sample], device: Device) -> (Tensor, Tensor, Tensor) {
+    let n = samples.len() as i64;
+    let features: Vec<f32> = samples.iter().flat_map(|s| s.features.clone()).collect();
+    let policies: Vec<f32> = samples.iter().flat_map(|s| s.dense_policy()).collect();
+    let values: Vec<f32> = samples.iter().map(|s| s.reward).collect();
+    (
+        Tensor::of_slice(&features)
+            .view([n, FEATURE_SIZE as i64])
+            .to_device(device),
+        Tensor::of_slice(&policies)
+            .view([n, ACTION_SIZE as i64])
+            .to_device(device),
+        Tensor::of_slice(&values).to_device(device),
+    )
+}
+
+// Hyperparameters for training, which we record with each checkpoint
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrainConfig {
+    pub learning_rate: f64,
+    pub batch_size: usize,
+    pub value_weight: f64, // how much the value loss counts relative to the policy loss
+}
+
+impl Default for TrainConfig {
+    fn default() -> TrainConfig {
+        TrainConfig {
+            learning_rate: 1e-3,
+            batch_size: 256,
+            value_weight: 1.0,
+        }
+    }
+}
+
+// Adam, written out so that its moments can be saved with a checkpoint and restored when
+// training resumes. The optimizers in tch can't save their state.
+pub struct Adam {
+    variables: Vec<(String, Tensor)>, // sorted by name, so they line up with saved moments
+    first: Vec<Tensor>,               // running mean of the gradient
+    second: Vec<Tensor>,              // running mean of the squared gradient
+    step: i64,
+    pub learning_rate: f64,
+}
+
+const ADAM_BETA1: f64 = 0.9;
+const ADAM_BETA2: f64 = 0.999;
+const ADAM_EPSILON: f64 = 1e-8;
+
+impl Adam {
+    pub fn new(vs: &nn::VarStore, learning_rate: f64) -> Adam {
+        let mut variables: Vec<(String, Tensor)> = vs.variables().into_iter().collect();
+        variables.sort_by(|a, b| a.0.cmp(&b.0));
+        let zeros = || variables.iter().map(|(_, v)| v.zeros_like()).collect();
+        Adam {
+            first: zeros(),
+            second: zeros(),
+            variables,
+            step: 0,
+            learning_rate,
+        }
+    }
+
+    pub fn backward_step(&mut self, loss: &Tensor) {
+        for (_, variable) in &mut self.variables {
+            variable.zero_grad();
+        }
+        loss.backward();
+        self.step += 1;
+        let correction1 = 1.0 - ADAM_BETA1.powi(self.step as i32);
+        let correction2 = 1.0 - ADAM_BETA2.powi(self.step as i32);
+        tch::no_grad(|| {
+            let moments = self.first.iter_mut().zip(&mut self.second);
+            for ((_, variable), (first, second)) in self.variables.iter_mut().zip(moments) {
+                let grad = variable.grad();
+                if !grad.defined() {
+                    continue;
+                }
+                *first = &*first * ADAM_BETA1 + &grad * (1.0 - ADAM_BETA1);
+                *second = &*second * ADAM_BETA2 + &grad * &grad * (1.0 - ADAM_BETA2);
+                let update = (&*first / correction1)
+                    / ((&*second / correction2).sqrt() + ADAM_EPSILON)
+                    * self.learning_rate;
+                *variable -= update;
+            }
+        });
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), TchError> {
+        let mut named = vec![("step".to_string(), Tensor::of_slice(&[self.step]))];
+        for (i, (name, _)) in self.variables.iter().enumerate() {
+            named.push((format!("first.{}", name), self.first[i].shallow_clone()));
+            named.push((format!("second.{}", name), self.second[i].shallow_clone()));
+        }
+        Tensor::save_multi(&named, path)
+    }
+
+    // Restores the moments and step count from save, keeping our learning rate
+    pub fn load(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut saved: std::collections::HashMap<String, Tensor> = Tensor::load_multi(path)
+            .map_err(io::Error::other)?
+            .into_iter()
+            .collect();
+        let mut take = |name: String| {
+            saved
+                .remove(&name)
+                .ok_or_else(|| invalid(format!("optimizer state is missing {}", name)))
+        };
+        let step = i64::from(&take("step".to_string())?);
+        for (i, (name, variable)) in self.variables.iter().enumerate() {
+            let first = take(format!("first.{}", name))?;
+            let second = take(format!("second.{}", name))?;
+            if first.size() != variable.size() || second.size() != variable.size() {
+                return Err(invalid(format!(
+                    "optimizer state for {} has the wrong shape",
+                    name
+                )));
+            }
+            self.first[i] = first.to_device(variable.device());
+            self.second[i] = second.to_device(variable.device());
+        }
+        self.step = step;
+        Ok(())
+    }
+}
+
+impl Model {
+    // The cross-entropy policy loss and the MSE value loss for a batch
+    pub fn losses(&self, xs: &Tensor, policies: &Tensor, values: &Tensor) -> (Tensor, Tensor) {
+        let (logits, predicted) = self.network.forward(xs);
+        let policy_loss = -(policies * logits.log_softmax(-1, Kind::Float))
+            .sum_dim_intlist([-1].as_slice(), false, Kind::Float)
+            .mean(Kind::Float);
+        let value_loss = predicted.mse_loss(values, Reduction::Mean);
+        (policy_loss, value_loss)
+    }
+
+    pub fn optimizer(&self, config: &TrainConfig) -> Adam {
+        Adam::new(&self.vs, config.learning_rate)
+    }
+
+    // Does one pass over the samples in a random order.
+    // Returns the average policy and value losses.
+    pub fn train_epoch(
+        &self,
+        optimizer: &mut Adam,
+        samples: &[Sample],
+        config: &TrainConfig,
+    ) -> (f64, f64) {
+        let mut order: Vec<&Sample> = samples.iter().collect();
+        rng::with(|rng| order.shuffle(rng));
+
+        let mut policy_total = 0.0;
+        let mut value_total = 0.0;
+        let mut batches = 0;
+        for batch in order.chunks(config.batch_size) {
+            let (xs, policies, values) = samples_tensors(batch, self.device());
+            let (policy_loss, value_loss) = self.losses(&xs, &policies, &values);
+            optimizer.backward_step(&(&policy_loss + &value_loss * config.value_weight));
+            policy_total += f64::from(&policy_loss);
+            value_total += f64::from(&value_loss);
+            batches += 1;
+        }
+        (policy_total / batches as f64, value_total / batches as f64)
+    }
+}
+
+// Predicts P(kill by turn n) for each turn, trained on how self-play games actually ended.
+// It shares the shape of Network's trunk, with one sigmoid output per turn.
+pub struct WinModel {
+    pub vs: nn::VarStore,
+    fc1: nn::Linear,
+    fc2: nn::Linear,
+    win: nn::Linear,
+}
+
+impl WinModel {
+    pub fn new(device: Device) -> WinModel {
+        let vs = nn::VarStore::new(device);
+        let root = vs.root();
+        let config = Default::default();
+        WinModel {
+            fc1: nn::linear(&root / "fc1", FEATURE_SIZE as i64, HIDDEN_SIZE, config),
+            fc2: nn::linear(&root / "fc2", HIDDEN_SIZE, HIDDEN_SIZE, config),
+            win: nn::linear(&root / "win", HIDDEN_SIZE, WIN_TURNS as i64, config),
+            vs,
+        }
+    }
+
+    // Returns logits with shape [batch, WIN_TURNS]
+    pub fn forward(&self, xs: &Tensor) -> Tensor {
+        let hidden = self.fc2.forward(&self.fc1.forward(xs).relu()).relu();
+        self.win.forward(&hidden)
+    }
+
+    pub fn optimizer(&self, config: &TrainConfig) -> Adam {
+        Adam::new(&self.vs, config.learning_rate)
+    }
+
+    // Does one pass over the samples in a random order.
+    // Returns the average binary cross-entropy loss.
+    pub fn train_epoch(
+        &self,
+        optimizer: &mut Adam,
+        samples: &[Sample],
+        config: &TrainConfig,
+    ) -> f64 {
+        let mut order: Vec<&Sample> = samples.iter().collect();
+        rng::with(|rng| order.shuffle(rng));
+
+        let device = self.vs.device();
+        let mut total = 0.0;
+        let mut batches = 0;
+        for batch in order.chunks(config.batch_size) {
+            let n = batch.len() as i64;
+            let (xs, _, _) = samples_tensors(batch, device);
+            let targets: Vec<f32> = batch.iter().flat_map(|s| s.win_targets()).collect();
+            let targets = Tensor::of_slice(&targets)
+                .view([n, WIN_TURNS as i64])
+                .to_device(device);
+            let loss = self
+                .forward(&xs)
+                .binary_cross_entropy_with_logits::<Tensor>(&targets, None, None, Reduction::Mean);
+            optimizer.backward_step(&loss);
+            total += f64::from(&loss);
+            batches += 1;
+        }
+        total / batches as f64
+    }
+
+    #[cfg(feature = "mlp")]
+    pub fn to_mlp(&self) -> WinMlp {
+        WinMlp::new(
+            vec![
+                export_layer("fc1", &self.fc1),
+                export_layer("fc2", &self.fc2),
+            ],
+            export_layer("win", &self.win),
+        )
+        .unwrap()
+    }
+}
+
+// A saved model, along with what it takes to reproduce or continue training it.
+// Checkpoint n is stored as checkpoint-n.ot for the weights and checkpoint-n.meta
+// for this metadata, as "key = value" lines.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Checkpoint {
+    pub version: usize,
+    pub encoder_version: u32,
+    pub deck: String,
+    pub epochs: usize, // total epochs trained, including before any resume
+    pub config: TrainConfig,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl Checkpoint {
+    fn path(dir: impl AsRef<Path>, version: usize, extension: &str) -> PathBuf {
+        dir.as_ref()
+            .join(format!("checkpoint-{:05}.{}", version, extension))
+    }
+
+    pub fn weights_path(&self, dir: impl AsRef<Path>) -> PathBuf {
+        Checkpoint::path(dir, self.version, "ot")
+    }
+
+    pub fn optimizer_path(&self, dir: impl AsRef<Path>) -> PathBuf {
+        Checkpoint::path(dir, self.version, "adam")
+    }
+
+    pub fn to_meta_string(&self) -> String {
+        format!(
+            "version = {}\nencoder_version = {}\ndeck = {}\nepochs = {}\n\
+             learning_rate = {}\nbatch_size = {}\nvalue_weight = {}\n",
+            self.version,
+            self.encoder_version,
+            self.deck,
+            self.epochs,
+            self.config.learning_rate,
+            self.config.batch_size,
+            self.config.value_weight,
+        )
+    }
+
+    pub fn from_meta_string(s: &str) -> io::Result<Checkpoint> {
+        let mut fields = std::collections::HashMap::new();
+        for line in s.lines().filter(|line| !line.trim().is_empty()) {
+            match line.split_once('=') {
+                Some((key, value)) => fields.insert(key.trim(), value.trim()),
+                None => return Err(invalid(format!("bad checkpoint line: {}", line))),
+            };
+        }
+        let get = |key: &str| {
+            fields
+                .get(key)
+                .copied()
+                .ok_or_else(|| invalid(format!("checkpoint is missing {}", key)))
+        };
+        let parse_error = |key: &str| invalid(format!("bad checkpoint value for {}", key));
+        Ok(Checkpoint {
+            version: get("version")?
+                .parse()
+                .map_err(|_| parse_error("version"))?,
+            encoder_version: get("encoder_version")?
+                .parse()
+                .map_err(|_| parse_error("encoder_version"))?,
+            deck: get("deck")?.to_string(),
+            epochs: get("epochs")?.parse().map_err(|_| parse_error("epochs"))?,
+            config: TrainConfig {
+                learning_rate: get("learning_rate")?
+                    .parse()
+                    .map_err(|_| parse_error("learning_rate"))?,
+                batch_size: get("batch_size")?
+                    .parse()
+                    .map_err(|_| parse_error("batch_size"))?,
+                value_weight: get("value_weight")?
+                    .parse()
+                    .map_err(|_| parse_error("value_weight"))?,
+            },
+        })
+    }
+
+    // Saves the weights, the optimizer state, and the metadata into dir
+    pub fn save(&self, dir: impl AsRef<Path>, model: &Model, optimizer: &Adam) -> io::Result<()> {
+        fs::create_dir_all(&dir)?;
+        model
+            .vs
+            .save(self.weights_path(&dir))
+            .map_err(io::Error::other)?;
+        optimizer
+            .save(self.optimizer_path(&dir))
+            .map_err(io::Error::other)?;
+        fs::write(
+            Checkpoint::path(&dir, self.version, "meta"),
+            self.to_meta_string(),
+        )
+    }
+
+    // Loads the weights for this checkpoint, checking that they match our encoder
+    pub fn load_model(&self, dir: impl AsRef<Path>, device: Device) -> io::Result<Model> {
+        if self.encoder_version != ENCODER_VERSION {
+            return Err(invalid(format!(
+                "checkpoint uses encoder version {} but we have {}",
+                self.encoder_version, ENCODER_VERSION
+            )));
+        }
+        Model::load(self.weights_path(&dir), device).map_err(io::Error::other)
+    }
+
+    // The checkpoint with the highest version in dir, if there is one
+    pub fn latest(dir: impl AsRef<Path>) -> io::Result<Option<Checkpoint>> {
+        if !dir.as_ref().exists() {
+            return Ok(None);
+        }
+        let mut latest: Option<Checkpoint> = None;
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let name = path.file_name().unwrap().to_string_lossy();
+            if !name.starts_with("checkpoint-") || !name.ends_with(".meta") {
+                continue;
+            }
+            let checkpoint = Checkpoint::from_meta_string(&fs::read_to_string(&path)?)?;
+            if latest
+                .as_ref()
+                .is_none_or(|l| checkpoint.version > l.version)
+            {
+                latest = Some(checkpoint);
+            }
+        }
+        Ok(latest)
+    }
+
+    pub fn load(dir: impl AsRef<Path>, version: usize) -> io::Result<Checkpoint> {
+        Checkpoint::from_meta_string(&fs::read_to_string(Checkpoint::path(dir, version, "meta"))?)
+    }
+
+    // The checkpoint that has passed gating most recently, if any.
+    // We record it as best.meta, a copy of its metadata.
+    pub fn best(dir: impl AsRef<Path>) -> io::Result<Option<Checkpoint>> {
+        let path = dir.as_ref().join("best.meta");
+        if !path.exists() {
+            return Ok(None);
+        }
+        Checkpoint::from_meta_string(&fs::read_to_string(path)?).map(Some)
+    }
+
+    pub fn promote(&self, dir: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(dir.as_ref().join("best.meta"), self.to_meta_string())
+    }
+}
+
+// A shared model can act as both the MCTS policy and its leaf evaluator
+impl Policy for Rc<Model> {
+    fn evaluate(&mut self, game: &Game, actions: &[Action]) -> Vec<f32> {
+        self.evaluate_batch(&[(game, actions)]).pop().unwrap()
+    }
+
+    fn evaluate_batch(&mut self, batch: &[(&Game, &[Action])]) -> Vec<Vec<f32>> {
+        let games: Vec<&Game> = batch.iter().map(|(game, _)| *game).collect();
+        let actions: Vec<&[Action]> = batch.iter().map(|(_, actions)| *actions).collect();
+        self.predict(&games, &actions).0
+    }
+}
+
+impl ValueEstimator for Rc<Model> {
+    fn estimate(&mut self, game: &Game) -> f32 {
+        self.estimate_batch(&[game])[0]
+    }
+
+    fn estimate_batch(&mut self, games: &[&Game]) -> Vec<f32> {
+        let actions: Vec<Vec<Action>> = games.iter().map(|g| g.non_kill_actions()).collect();
+        let actions: Vec<&[Action]> = actions.iter().map(|a| a.as_slice()).collect();
+        self.predict(games, &actions).1
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::card::PANDA_DECK;
+    use crate::mcts::MCTS;
+
+    #[test]
+    fn cpu_policy() {
+        let mut model = Rc::new(Model::new(Device::Cpu));
+        let game = Game::new_going_first(PANDA_DECK);
+        let actions = game.non_kill_actions();
+        let policy = model.evaluate(&game, &actions);
+        assert_eq!(policy.len(), actions.len());
+        let total: f32 = policy.iter().sum();
+        assert!((total - 1.0).abs() < 1e-4);
+    }
+
+    #[cfg(feature = "mlp")]
+    #[test]
+    fn exported_mlp_matches() {
+        let model = Model::new(Device::Cpu);
+        let mlp = model.to_mlp();
+        let game = Game::new_going_first(PANDA_DECK);
+        let actions = game.non_kill_actions();
+        let (policies, values) = model.predict(&[&game], &[&actions]);
+        let (policy, value) = mlp.predict(&game, &actions);
+        assert!((value - values[0]).abs() < 1e-4);
+        for (a, b) in policy.iter().zip(&policies[0]) {
+            assert!((a - b).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn win_model_learns() {
+        let model = WinModel::new(Device::Cpu);
+        let samples =
+            crate::selfplay::play_game(PANDA_DECK, 10, crate::mcts::Reward::MeanTurn, &mut || {
+                MCTS::new(crate::mcts::escape_policy)
+            });
+        let config = TrainConfig {
+            batch_size: 8,
+            ..TrainConfig::default()
+        };
+        let mut optimizer = model.optimizer(&config);
+        let first = model.train_epoch(&mut optimizer, &samples, &config);
+        let mut last = first;
+        for _ in 0..20 {
+            last = model.train_epoch(&mut optimizer, &samples, &config);
+        }
+        assert!(last < first);
+    }
+
+    #[test]
+    fn checkpoint_meta_round_trip() {
+        let checkpoint = Checkpoint {
+            version: 3,
+            encoder_version: ENCODER_VERSION,
+            deck: "panda".to_string(),
+            epochs: 12,
+            config: TrainConfig::default(),
+        };
+        let parsed = Checkpoint::from_meta_string(&checkpoint.to_meta_string()).unwrap();
+        assert_eq!(parsed, checkpoint);
+    }
+
+    #[test]
+    fn training_on_cpu() {
+        let model = Model::new(Device::Cpu);
+        let samples =
+            crate::selfplay::play_game(PANDA_DECK, 10, crate::mcts::Reward::MeanTurn, &mut || {
+                MCTS::new(crate::mcts::escape_policy)
+            });
+        let config = TrainConfig {
+            batch_size: 8,
+            ..TrainConfig::default()
+        };
+        let mut optimizer = model.optimizer(&config);
+        let (first_policy, first_value) = model.train_epoch(&mut optimizer, &samples, &config);
+        let mut last = (first_policy, first_value);
+        for _ in 0..20 {
+            last = model.train_epoch(&mut optimizer, &samples, &config);
+        }
+        assert!(last.0 + last.1 < first_policy + first_value);
+    }
+
+    #[test]
+    fn optimizer_state_round_trip() {
+        let model = Model::new(Device::Cpu);
+        let samples =
+            crate::selfplay::play_game(PANDA_DECK, 10, crate::mcts::Reward::MeanTurn, &mut || {
+                MCTS::new(crate::mcts::escape_policy)
+            });
+        let config = TrainConfig {
+            batch_size: 8,
+            ..TrainConfig::default()
+        };
+        let mut optimizer = model.optimizer(&config);
+        model.train_epoch(&mut optimizer, &samples, &config);
+        let path = std::env::temp_dir().join(format!("goldfish-{}.adam", std::process::id()));
+        optimizer.save(&path).unwrap();
+        let mut loaded = Adam::new(&model.vs, 0.5);
+        loaded.load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.step, optimizer.step);
+        assert_eq!(loaded.learning_rate, 0.5);
+        for (a, b) in loaded.second.iter().zip(&optimizer.second) {
+            assert!(a.equal(b));
+        }
+    }
 
     #[test]
-    fn gpu_available() {
-        assert!(cuda_available());
+    fn mcts_with_model() {
+        let model = Rc::new(Model::new(default_device()));
+        let game = Game::new_going_first(PANDA_DECK);
+        let mut mcts = MCTS::new(model.clone())
+            .with_evaluator(model)
+            .with_batch_size(4);
+        mcts.search(&game, 10);
+        assert!(game.non_kill_actions().contains(&mcts.best_action(&game)));
     }
 }
@@ -0,0 +1,252 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::card::Card;
+use crate::game::Game;
+use crate::sim::GameResult;
+use crate::stats::{Proportion, Summary};
+
+// The cards we kept before the first turn, not counting The Coin for going second
+// or the first turn's draw
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Opener {
+    pub going_first: bool,
+    pub cards: Vec<Card>, // sorted, so that the same cards make the same opener
+}
+
+impl Opener {
+    // Reads the opener from the first turn of a game from new_going_first or
+    // new_going_second. Going first we hold 3 cards and a draw, and going second we hold
+    // 4 cards, The Coin, and a draw. A short deck can deal fewer.
+    pub fn of(game: &Game, going_first: bool) -> Opener {
+        let size = if going_first { 3 } else { 4 };
+        let mut cards: Vec<Card> = game
+            .hand
+            .iter()
+            .take(size)
+            .filter(|ci| ci.from_deck)
+            .map(|ci| ci.card)
+            .collect();
+        cards.sort_by_key(|c| *c as usize);
+        Opener { going_first, cards }
+    }
+
+    pub fn going(&self) -> &'static str {
+        if self.going_first {
+            "first"
+        } else {
+            "second"
+        }
+    }
+
+    pub fn cards_string(&self) -> String {
+        cards_string(&self.cards)
+    }
+
+    // Whether the opener has all of these cards, counting copies
+    pub fn contains(&self, cards: &[Card]) -> bool {
+        let mut left = self.cards.clone();
+        cards
+            .iter()
+            .all(|card| match left.iter().position(|c| c == card) {
+                Some(i) => {
+                    left.remove(i);
+                    true
+                }
+                None => false,
+            })
+    }
+}
+
+pub fn cards_string(cards: &[Card]) -> String {
+    cards
+        .iter()
+        .map(|c| c.to_string())
+        .collect::<Vec<String>>()
+        .join(" + ")
+}
+
+// Parses cards joined with "+", like "Foxy + Scabbs"
+pub fn parse_cards(s: &str) -> Result<Vec<Card>, String> {
+    s.split('+')
+        .filter(|name| !name.trim().is_empty())
+        .map(|name| Card::parse(name).ok_or(format!("unknown card: {}", name.trim())))
+        .collect()
+}
+
+// How sampled openers went
+pub struct OpenerTable {
+    pub games: Vec<(Opener, GameResult)>,
+    pub max_turns: i32, // the turn we gave up on
+}
+
+impl OpenerTable {
+    // The games for each distinct opener, most common first
+    pub fn compositions(&self) -> Vec<(&Opener, Vec<&GameResult>)> {
+        let mut groups: HashMap<&Opener, Vec<&GameResult>> = HashMap::new();
+        for (opener, result) in &self.games {
+            groups.entry(opener).or_default().push(result);
+        }
+        let mut answer: Vec<_> = groups.into_iter().collect();
+        answer.sort_by(|(a, a_games), (b, b_games)| {
+            b_games
+                .len()
+                .cmp(&a_games.len())
+                .then(b.going_first.cmp(&a.going_first))
+                .then(a.cards_string().cmp(&b.cards_string()))
+        });
+        answer
+    }
+
+    // The chance of killing by the given turn when the opener has these cards, going first,
+    // second, or either when going_first is None
+    pub fn kill_chance(&self, cards: &[Card], going_first: Option<bool>, turn: i32) -> Proportion {
+        let games: Vec<&GameResult> = self
+            .games
+            .iter()
+            .filter(|(opener, _)| going_first.is_none_or(|g| g == opener.going_first))
+            .filter(|(opener, _)| opener.contains(cards))
+            .map(|(_, r)| r)
+            .collect();
+        Proportion {
+            successes: games
+                .iter()
+                .filter(|r| r.won && r.kill_turn <= turn)
+                .count(),
+            n: games.len(),
+        }
+    }
+
+    // The most common openers, with their mean kill turn and chance to kill by the turn
+    pub fn table(&self, top: usize, turn: i32) -> String {
+        let mut out = String::new();
+        writeln!(
+            out,
+            "{:<6} {:>5} {:>5} {:>8}  hand",
+            "going",
+            "games",
+            "mean",
+            format!("by t{}", turn)
+        )
+        .unwrap();
+        for (opener, games) in self.compositions().into_iter().take(top) {
+            let turns: Vec<f64> = games.iter().map(|r| r.kill_turn as f64).collect();
+            let kills = games
+                .iter()
+                .filter(|r| r.won && r.kill_turn <= turn)
+                .count();
+            writeln!(
+                out,
+                "{:<6} {:>5} {:>5.2} {:>7.0}%  {}",
+                opener.going(),
+                games.len(),
+                Summary::new(&turns).mean,
+                100.0 * kills as f64 / games.len() as f64,
+                opener.cards_string()
+            )
+            .unwrap();
+        }
+        out
+    }
+
+    // One row for each distinct opener, with how many games killed on each turn
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from("going,hand,games,mean_kill_turn");
+        for turn in 1..self.max_turns {
+            write!(out, ",turn_{}", turn).unwrap();
+        }
+        out.push_str(",lost\n");
+        for (opener, games) in self.compositions() {
+            let turns: Vec<f64> = games.iter().map(|r| r.kill_turn as f64).collect();
+            write!(
+                out,
+                "{},{},{},{:.3}",
+                opener.going(),
+                opener.cards_string(),
+                games.len(),
+                Summary::new(&turns).mean
+            )
+            .unwrap();
+            for turn in 1..self.max_turns {
+                let kills = games
+                    .iter()
+                    .filter(|r| r.won && r.kill_turn == turn)
+                    .count();
+                write!(out, ",{}", kills).unwrap();
+            }
+            writeln!(out, ",{}", games.iter().filter(|r| !r.won).count()).unwrap();
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::card::PANDA_DECK;
+    use crate::cardstats::CardStats;
+    use crate::rng;
+
+    fn result(kill_turn: i32) -> GameResult {
+        GameResult {
+            won: kill_turn < 10,
+            kill_turn,
+            cards: CardStats::default(),
+        }
+    }
+
+    fn opener(going_first: bool, cards: &[Card]) -> Opener {
+        let mut cards = cards.to_vec();
+        cards.sort_by_key(|c| *c as usize);
+        Opener { going_first, cards }
+    }
+
+    #[test]
+    fn reading_openers() {
+        rng::seed(5);
+        let first = Opener::of(&Game::new_going_first(PANDA_DECK), true);
+        assert!(first.going_first);
+        assert_eq!(first.cards.len(), 3);
+        let second = Opener::of(&Game::new_going_second(PANDA_DECK), false);
+        assert!(!second.going_first);
+        assert_eq!(second.cards.len(), 4);
+        assert!(second.contains(&second.cards[..2]));
+        assert!(!second.contains(&[Card::Unknown]));
+
+        // Two cards can't deal a whole opener, and The Coin isn't part of it
+        let short = Opener::of(&Game::new_going_second(&[Card::Foxy, Card::Shark]), false);
+        assert_eq!(short, opener(false, &[Card::Foxy, Card::Shark]));
+    }
+
+    #[test]
+    fn tables_and_queries() {
+        let foxy_scabbs = opener(true, &[Card::Foxy, Card::Scabbs, Card::Evasion]);
+        let foxy = opener(true, &[Card::Foxy, Card::Evasion, Card::Evasion]);
+        let table = OpenerTable {
+            games: vec![
+                (foxy_scabbs.clone(), result(5)),
+                (foxy_scabbs.clone(), result(6)),
+                (foxy.clone(), result(10)),
+                (opener(false, &[Card::Foxy; 4]), result(4)),
+            ],
+            max_turns: 10,
+        };
+        assert_eq!(table.compositions()[0].0, &foxy_scabbs);
+        let both = parse_cards("foxy + Scabbs Cutterbutter").unwrap();
+        assert_eq!(table.kill_chance(&both, Some(true), 5).rate(), 0.5);
+        assert_eq!(
+            table.kill_chance(&[Card::Foxy], Some(true), 6).rate(),
+            2.0 / 3.0
+        );
+        assert_eq!(table.kill_chance(&[Card::Foxy], None, 6).rate(), 0.75);
+        assert_eq!(table.kill_chance(&both, Some(false), 6).n, 0);
+        assert!(parse_cards("Foxy + Nobody").is_err());
+
+        let csv = table.to_csv();
+        assert!(csv.starts_with("going,hand,games,mean_kill_turn,turn_1,"));
+        assert!(csv.contains(
+            "first,Evasion + Foxy Fraud + Scabbs Cutterbutter,2,5.500,0,0,0,0,1,1,0,0,0,0\n"
+        ));
+        assert!(table.table(5, 5).contains("Foxy Fraud + Foxy Fraud"));
+    }
+}
@@ -1,14 +1,13 @@
 use crate::card::Card;
 use crate::game::{Action, Game};
+use crate::rng;
 use rand::seq::SliceRandom;
 
 // EscapeBot plays according to some shallow, hand-coded heuristics
 pub fn escape_bot_action(game: &Game) -> Action {
     if !game.fish.is_empty() {
-        return *game
-            .non_kill_actions()
-            .choose(&mut rand::thread_rng())
-            .unwrap();
+        let actions = game.non_kill_actions();
+        return *rng::with(|rng| actions.choose(rng)).unwrap();
     }
 
     let plays = game.plays();
@@ -17,11 +16,7 @@ pub fn escape_bot_action(game: &Game) -> Action {
         // Try to play any useful combo cards we have
         for play in &plays {
             let ci = game.hand[play.index];
-            let useful = match ci.card {
-                Card::Swindle => true,
-                Card::GoneFishin => true,
-                _ => false,
-            };
+            let useful = matches!(ci.card, Card::Swindle | Card::GoneFishin);
             if useful {
                 return Action::Play(*play);
             }
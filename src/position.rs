@@ -0,0 +1,131 @@
+use crate::card::{parse_deck, Card, CardInstance};
+use crate::game::Game;
+
+// Any more and Game would burn them, like a full hand does
+const MAX_HAND: usize = 10;
+
+// Reads a position in the same "key: value" lines that a Game displays as, like
+//   hand: Foxy Fraud, Shadowstep, Potion of Illusion (-1)
+//   board: Mailbox Dancer
+//   mana: 6
+//   life: 30
+// Also accepts a deck, and the hidden counters storm, foxy, scabbs, next_scabbs, and
+// prep_pending. Anything left out keeps its value from Game::new.
+pub fn parse_position(text: &str) -> Result<Game, String> {
+    let mut game = Game::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = line.split_once(':').unwrap_or((line, ""));
+        let value = value.trim();
+        let number = || {
+            value
+                .parse::<i32>()
+                .map_err(|_| format!("expected a number for {}: {}", key, value))
+        };
+        match key.trim() {
+            "hand" => {
+                let hand = value
+                    .split(',')
+                    .filter(|s| !s.trim().is_empty())
+                    .map(parse_card_instance)
+                    .collect::<Result<Vec<_>, _>>()?;
+                if hand.len() > MAX_HAND {
+                    return Err(format!(
+                        "a hand holds at most {} cards, not {}",
+                        MAX_HAND,
+                        hand.len()
+                    ));
+                }
+                game.hand = hand;
+            }
+            "board" => game.board = parse_deck(value)?,
+            "deck" => game.deck = parse_deck(value)?,
+            "mana" => game.mana = number()?,
+            "life" => game.life = number()?,
+            "turn" => game.turn = number()?,
+            "storm" => game.storm = number()?,
+            "foxy" => game.foxy = number()?,
+            "scabbs" => game.scabbs = number()?,
+            "next_scabbs" => game.next_scabbs = number()?,
+            // Games display a pending Preparation as a bare "prep_pending"
+            "prep_pending" => {
+                game.prep_pending = match value {
+                    "" | "true" => true,
+                    "false" => false,
+                    _ => {
+                        return Err(format!(
+                            "expected true or false for prep_pending: {}",
+                            value
+                        ))
+                    }
+                }
+            }
+            key => return Err(format!("unknown position key: {}", key)),
+        }
+    }
+    Ok(game)
+}
+
+// A card in hand, with the suffixes that CardInstance displays with
+fn parse_card_instance(s: &str) -> Result<CardInstance, String> {
+    let mut name = s.trim();
+    let mut potion = false;
+    let mut tenwu = false;
+    let mut cost_reduction = 0;
+    while let Some((rest, suffix)) = name
+        .strip_suffix(')')
+        .and_then(|rest| rest.rsplit_once(" ("))
+    {
+        match suffix {
+            "potion" => potion = true,
+            "tenwu" => tenwu = true,
+            _ => match suffix.strip_prefix('-').and_then(|n| n.parse().ok()) {
+                Some(n) => cost_reduction = n,
+                None => return Err(format!("unknown card suffix: ({})", suffix)),
+            },
+        }
+        name = rest.trim_end();
+    }
+    let card = Card::parse(name).ok_or(format!("unknown card: {}", name))?;
+    let mut ci = CardInstance::new(&card);
+    ci.potion = potion;
+    ci.tenwu = tenwu;
+    ci.cost_reduction = cost_reduction;
+    Ok(ci)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn displayed_games_parse_back() {
+        let mut game = Game::new();
+        game.mana = 6;
+        game.life = 25;
+        game.storm = 2;
+        game.prep_pending = true;
+        game.board = vec![Card::Dancer];
+        game.add_cards_to_hand(vec![Card::Foxy, Card::Shadowstep].into_iter());
+        let mut ci = CardInstance::new(&Card::Pillager);
+        ci.potion = true;
+        ci.cost_reduction = 2;
+        game.add_card_instance_to_hand(ci);
+        let parsed = parse_position(&game.to_string()).unwrap();
+        assert_eq!(parsed.to_string(), game.to_string());
+        assert!(parsed == game);
+    }
+
+    #[test]
+    fn bad_positions() {
+        assert!(parse_position("mana: lots").is_err());
+        assert!(parse_position("hand: Foxy Fraud (gold)").is_err());
+        assert!(parse_position("graveyard: Foxy Fraud").is_err());
+        assert!(parse_position("prep_pending: no").is_err());
+        assert!(!parse_position("prep_pending: false").unwrap().prep_pending);
+        assert!(parse_position(&format!("hand: {}", ["Evasion"; 11].join(", "))).is_err());
+    }
+}
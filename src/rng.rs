@@ -0,0 +1,64 @@
+use std::cell::RefCell;
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+// All of our randomness comes from here, so that it can be seeded.
+// Each thread has its own generator, seeded from entropy until someone seeds it.
+thread_local! {
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
+
+// Reseeds this thread's generator, so that everything after this is reproducible
+pub fn seed(seed: u64) {
+    RNG.with(|rng| *rng.borrow_mut() = StdRng::seed_from_u64(seed));
+}
+
+// Runs f with this thread's generator
+pub fn with<T>(f: impl FnOnce(&mut StdRng) -> T) -> T {
+    RNG.with(|rng| f(&mut rng.borrow_mut()))
+}
+
+// Runs f with the provided generator standing in for this thread's generator.
+// This lets us keep separate streams, like the draws in the real game versus the
+// draws an agent imagines while searching, so that one doesn't disturb the other.
+pub fn scoped<T>(rng: &mut StdRng, f: impl FnOnce() -> T) -> T {
+    RNG.with(|cell| std::mem::swap(rng, &mut cell.borrow_mut()));
+    let answer = f();
+    RNG.with(|cell| std::mem::swap(rng, &mut cell.borrow_mut()));
+    answer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn seeding_is_reproducible() {
+        seed(7);
+        let a: u64 = with(|rng| rng.gen());
+        seed(7);
+        let b: u64 = with(|rng| rng.gen());
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn scoped_streams_are_independent() {
+        let mut stream = StdRng::seed_from_u64(1);
+        seed(2);
+        let first: u64 = scoped(&mut stream, || with(|rng| rng.gen()));
+        let outside: u64 = with(|rng| rng.gen());
+
+        // Consuming the thread's generator shouldn't affect the scoped stream
+        let mut expected = StdRng::seed_from_u64(1);
+        assert_eq!(first, expected.gen::<u64>());
+        assert_eq!(
+            scoped(&mut stream, || with(|rng| rng.gen::<u64>())),
+            expected.gen::<u64>()
+        );
+
+        seed(2);
+        assert_eq!(outside, with(|rng| rng.gen::<u64>()));
+    }
+}
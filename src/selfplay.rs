@@ -0,0 +1,276 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::card::Card;
+use crate::encoding::{encode_action, encode_game, ACTION_SIZE, ENCODER_VERSION, FEATURE_SIZE};
+use crate::game::{Game, Plan, SolverLimit};
+use crate::mcts::{Reward, MAX_TURNS, MCTS};
+use crate::sim::DEFAULT_SOLVER_NODES;
+
+// One decision from a self-play game
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sample {
+    pub features: Vec<f32>, // the encoded state, FEATURE_SIZE long
+
+    // The MCTS visit distribution, as (action index, probability) pairs
+    pub policy: Vec<(usize, f32)>,
+
+    pub reward: f32,    // the eventual reward of the game
+    pub kill_turn: i32, // the turn we killed on, or MAX_TURNS if we never did
+}
+
+// How many turns we estimate P(kill by turn n) for, from turn 1 up
+pub const WIN_TURNS: usize = MAX_TURNS as usize - 1;
+
+impl Sample {
+    // The visit distribution spread out over the whole action space
+    pub fn dense_policy(&self) -> Vec<f32> {
+        let mut dense = vec![0.0; ACTION_SIZE];
+        for (index, p) in &self.policy {
+            dense[*index] = *p;
+        }
+        dense
+    }
+
+    // Whether we had killed by turn i + 1, for each i, as targets for a win estimator
+    pub fn win_targets(&self) -> Vec<f32> {
+        (1..=WIN_TURNS)
+            .map(|turn| (self.kill_turn <= turn as i32) as i32 as f32)
+            .collect()
+    }
+}
+
+// Plays one game, searching each decision with a fresh MCTS from new_mcts.
+// Returns a sample for each decision.
+pub fn play_game(
+    deck: &[Card],
+    playouts: usize,
+    reward: Reward,
+    new_mcts: &mut dyn FnMut() -> MCTS,
+) -> Vec<Sample> {
+    let mut game = Game::new_going_random(deck);
+    let mut samples = Vec::new();
+
+    let won = loop {
+        // Check for lethal before searching, so that no sample comes from a won position,
+        // where MCTS has nothing to choose between
+        if game.turn_is_fresh() {
+            let limit = SolverLimit::Nodes(DEFAULT_SOLVER_NODES);
+            if let Plan::Win(_) = game.find_deterministic_win_within(limit) {
+                break true;
+            }
+        }
+        let mut mcts = new_mcts();
+        mcts.search(&game, playouts);
+        let stats = mcts.root_stats(&game);
+        let total: u32 = stats.iter().map(|s| s.visits).sum();
+        samples.push(Sample {
+            features: encode_game(&game),
+            policy: stats
+                .iter()
+                .map(|s| {
+                    (
+                        encode_action(&s.action),
+                        s.visits as f32 / total.max(1) as f32,
+                    )
+                })
+                .collect(),
+            reward: 0.0,
+            kill_turn: 0,
+        });
+
+        let action = mcts.best_action(&game);
+        game.take_action(&action);
+
+        if game.turn >= MAX_TURNS {
+            break false;
+        }
+    };
+
+    let kill_turn = game.turn.min(MAX_TURNS);
+    for sample in &mut samples {
+        sample.reward = reward.value(kill_turn as f32, won);
+        sample.kill_turn = kill_turn;
+    }
+    samples
+}
+
+// Shards are little-endian binary files:
+//   the magic bytes, then format version, encoder version, feature size, action size,
+//   and sample count as u32s, then each sample as its features as f32s, the number of
+//   policy entries as a u8, each policy entry as a u8 index and f32 probability,
+//   the reward as an f32, and the kill turn as an i32.
+const MAGIC: &[u8; 4] = b"GFSP";
+const FORMAT_VERSION: u32 = 1;
+
+pub(crate) fn write_u32(w: &mut impl Write, x: u32) -> io::Result<()> {
+    w.write_all(&x.to_le_bytes())
+}
+
+pub(crate) fn write_f32(w: &mut impl Write, x: f32) -> io::Result<()> {
+    w.write_all(&x.to_le_bytes())
+}
+
+pub(crate) fn read_u32(r: &mut impl Read) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+pub(crate) fn read_f32(r: &mut impl Read) -> io::Result<f32> {
+    let mut buf = [0; 4];
+    r.read_exact(&mut buf)?;
+    Ok(f32::from_le_bytes(buf))
+}
+
+pub(crate) fn read_u8(r: &mut impl Read) -> io::Result<u8> {
+    let mut buf = [0; 1];
+    r.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+pub(crate) fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+pub fn write_shard(path: impl AsRef<Path>, samples: &[Sample]) -> io::Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    w.write_all(MAGIC)?;
+    for x in [
+        FORMAT_VERSION,
+        ENCODER_VERSION,
+        FEATURE_SIZE as u32,
+        ACTION_SIZE as u32,
+        samples.len() as u32,
+    ] {
+        write_u32(&mut w, x)?;
+    }
+    for sample in samples {
+        assert_eq!(sample.features.len(), FEATURE_SIZE);
+        for x in &sample.features {
+            write_f32(&mut w, *x)?;
+        }
+        w.write_all(&[sample.policy.len() as u8])?;
+        for (index, p) in &sample.policy {
+            w.write_all(&[*index as u8])?;
+            write_f32(&mut w, *p)?;
+        }
+        write_f32(&mut w, sample.reward)?;
+        w.write_all(&sample.kill_turn.to_le_bytes())?;
+    }
+    w.flush()
+}
+
+pub fn read_shard(path: impl AsRef<Path>) -> io::Result<Vec<Sample>> {
+    let mut r = BufReader::new(File::open(path)?);
+    let mut magic = [0; 4];
+    r.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid("not a self-play shard".to_string()));
+    }
+    let expected = [
+        ("format version", FORMAT_VERSION),
+        ("encoder version", ENCODER_VERSION),
+        ("feature size", FEATURE_SIZE as u32),
+        ("action size", ACTION_SIZE as u32),
+    ];
+    for (name, value) in expected {
+        let found = read_u32(&mut r)?;
+        if found != value {
+            return Err(invalid(format!(
+                "shard has {} {} but we expect {}",
+                name, found, value
+            )));
+        }
+    }
+
+    let count = read_u32(&mut r)?;
+    let mut samples = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let features = (0..FEATURE_SIZE)
+            .map(|_| read_f32(&mut r))
+            .collect::<io::Result<Vec<f32>>>()?;
+        let num_policy = read_u8(&mut r)?;
+        let policy = (0..num_policy)
+            .map(|_| Ok((read_u8(&mut r)? as usize, read_f32(&mut r)?)))
+            .collect::<io::Result<Vec<(usize, f32)>>>()?;
+        let reward = read_f32(&mut r)?;
+        let kill_turn = read_u32(&mut r)? as i32;
+        samples.push(Sample {
+            features,
+            policy,
+            reward,
+            kill_turn,
+        });
+    }
+    Ok(samples)
+}
+
+pub fn shard_path(dir: impl AsRef<Path>, index: usize) -> PathBuf {
+    dir.as_ref().join(format!("shard-{:05}.bin", index))
+}
+
+// All the shards in a directory, in order
+pub fn shard_paths(dir: impl AsRef<Path>) -> io::Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = path.file_name().unwrap().to_string_lossy();
+        if name.starts_with("shard-") && name.ends_with(".bin") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+// Reads every sample from every shard in a directory
+pub fn read_shards(dir: impl AsRef<Path>) -> io::Result<Vec<Sample>> {
+    let mut samples = Vec::new();
+    for path in shard_paths(dir)? {
+        samples.extend(read_shard(path)?);
+    }
+    Ok(samples)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::card::PANDA_DECK;
+    use crate::mcts::escape_policy;
+    use crate::rng;
+
+    #[test]
+    fn shard_round_trip() {
+        let samples = play_game(PANDA_DECK, 10, Reward::MeanTurn, &mut || {
+            MCTS::new(escape_policy)
+        });
+        assert!(!samples.is_empty());
+        for sample in &samples {
+            let total: f32 = sample.policy.iter().map(|(_, p)| p).sum();
+            assert!((total - 1.0).abs() < 1e-4);
+        }
+
+        let dir = std::env::temp_dir().join(format!("goldfish-shards-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        write_shard(shard_path(&dir, 0), &samples).unwrap();
+        write_shard(shard_path(&dir, 1), &samples[..1]).unwrap();
+        let read = read_shards(&dir).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(read.len(), samples.len() + 1);
+        assert_eq!(read[..samples.len()], samples[..]);
+    }
+
+    #[test]
+    fn seeded_games_are_reproducible() {
+        let play = || {
+            rng::seed(3);
+            play_game(PANDA_DECK, 10, Reward::MeanTurn, &mut || {
+                MCTS::new(escape_policy)
+            })
+        };
+        assert_eq!(play(), play());
+    }
+}
@@ -0,0 +1,231 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::agent::Agent;
+use crate::card::Card;
+use crate::cardstats::CardStats;
+use crate::cli::Args;
+use crate::game::{Game, Plan, SolverLimit};
+use crate::mcts::MAX_TURNS;
+use crate::rng;
+
+// How one game went
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct GameResult {
+    pub won: bool,
+    pub kill_turn: i32, // the turn we found a kill on, or the turn limit if we never did
+    pub cards: CardStats,
+}
+
+// When a simulated game stops
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Limits {
+    pub max_turns: i32,      // we give up on reaching this turn
+    pub solver: SolverLimit, // how hard to look for a deterministic win at each turn's start
+}
+
+// About a second of solving on a typical core
+pub const DEFAULT_SOLVER_NODES: u64 = 1_000_000;
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_turns: MAX_TURNS,
+            solver: SolverLimit::Nodes(DEFAULT_SOLVER_NODES),
+        }
+    }
+}
+
+impl Limits {
+    // Reads --turns, and --nodes or --time for the solver, falling back on the defaults
+    pub fn from_args(args: &Args, defaults: Limits) -> Result<Limits, String> {
+        let solver = match (args.get("nodes"), args.get("time")) {
+            (Some(_), Some(_)) => return Err("give --nodes or --time, not both".to_string()),
+            (Some(nodes), None) => SolverLimit::Nodes(nodes),
+            (None, Some(seconds)) => SolverLimit::Seconds(seconds),
+            (None, None) => defaults.solver,
+        };
+        Ok(Limits {
+            max_turns: args.get_or("turns", defaults.max_turns),
+            solver,
+        })
+    }
+}
+
+// Mixed into the seed for the agent's own randomness, so it differs from the game's
+const AGENT_SEED_MIX: u64 = 0x9e37_79b9_7f4a_7c15;
+
+// Multiplied by the continuation number and mixed into the seed for the later draws
+const CONTINUATION_SEED_MIX: u64 = 0xbf58_476d_1ce4_e5b9;
+
+// The first turn of the game for this seed, whether we went first, and the randomness for
+// the rest of the draws
+fn seeded_start(deck: &[Card], seed: u64) -> (Game, bool, StdRng) {
+    let mut game_rng = StdRng::seed_from_u64(seed);
+    // Like Game::new_going_random, but remembering which way it went
+    let (game, going_first) = rng::scoped(&mut game_rng, || {
+        let going_first: bool = rng::with(|rng| rng.gen());
+        if going_first {
+            (Game::new_going_first(deck), true)
+        } else {
+            (Game::new_going_second(deck), false)
+        }
+    });
+    (game, going_first, game_rng)
+}
+
+// The game that play_seeded_game starts from for this seed, and whether we go first
+pub fn starting_game(deck: &[Card], seed: u64) -> (Game, bool) {
+    let (game, going_first, _) = seeded_start(deck, seed);
+    (game, going_first)
+}
+
+// Plays a game to the end with the provided agent.
+// The seed determines whether we go first, the opening hand, and the draws, using a stream
+// of randomness that the agent's searches don't touch. So two agents given the same seed
+// start from the same opening and draw from the same sequence of random numbers.
+pub fn play_seeded_game(
+    deck: &[Card],
+    seed: u64,
+    agent: &mut dyn Agent,
+    limits: &Limits,
+) -> GameResult {
+    play_seeded_continuation(deck, seed, 0, agent, limits)
+}
+
+// Like play_seeded_game, but each continuation of a seed starts from the same first turn
+// and then draws differently. Continuation 0 is the game play_seeded_game plays.
+pub fn play_seeded_continuation(
+    deck: &[Card],
+    seed: u64,
+    continuation: u64,
+    agent: &mut dyn Agent,
+    limits: &Limits,
+) -> GameResult {
+    let (mut game, _, mut game_rng) = seeded_start(deck, seed);
+    let mix = continuation.wrapping_mul(CONTINUATION_SEED_MIX);
+    if continuation > 0 {
+        game_rng = StdRng::seed_from_u64(seed ^ mix);
+    }
+    game.track_cards(deck)
+        .expect("the game starts from this deck");
+    rng::seed(seed ^ AGENT_SEED_MIX ^ mix);
+    agent.reset();
+
+    loop {
+        let action = game.untracked(|game| agent.act(game));
+        rng::scoped(&mut game_rng, || game.take_action(&action));
+        game.untracked(|game| agent.observe(&action, game));
+
+        if game.turn >= limits.max_turns {
+            game.finish_tracking(&[]);
+            return GameResult {
+                won: false,
+                kill_turn: limits.max_turns,
+                cards: *game.card_stats().unwrap(),
+            };
+        }
+        if game.turn_is_fresh() {
+            let plan = game.untracked(|game| game.find_deterministic_win_within(limits.solver));
+            if let Plan::Win(plays) = plan {
+                rng::scoped(&mut game_rng, || game.finish_tracking(&plays));
+                return GameResult {
+                    won: true,
+                    kill_turn: game.turn,
+                    cards: *game.card_stats().unwrap(),
+                };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::card::PANDA_DECK;
+    use crate::cardstats::CardCounts;
+    use crate::mcts::random_action;
+    use crate::player::escape_bot_action;
+
+    #[test]
+    fn same_seed_same_opening() {
+        let mut first_a = None;
+        let mut first_b = None;
+        play_seeded_game(
+            PANDA_DECK,
+            3,
+            &mut |game: &Game| {
+                first_a.get_or_insert(game.clone());
+                escape_bot_action(game)
+            },
+            &Limits::default(),
+        );
+        play_seeded_game(
+            PANDA_DECK,
+            3,
+            &mut |game: &Game| {
+                first_b.get_or_insert(game.clone());
+                random_action(game)
+            },
+            &Limits::default(),
+        );
+        assert!(first_a.unwrap() == first_b.unwrap());
+    }
+
+    #[test]
+    fn seeded_games_are_reproducible() {
+        let a = play_seeded_game(PANDA_DECK, 11, &mut escape_bot_action, &Limits::default());
+        let b = play_seeded_game(PANDA_DECK, 11, &mut escape_bot_action, &Limits::default());
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn continuations_share_the_first_turn() {
+        let limits = Limits::default();
+        let mut results = Vec::new();
+        for continuation in 0..5 {
+            let mut first = None;
+            let mut agent = |game: &Game| {
+                first.get_or_insert(game.clone());
+                escape_bot_action(game)
+            };
+            results.push(play_seeded_continuation(
+                PANDA_DECK,
+                7,
+                continuation,
+                &mut agent,
+                &limits,
+            ));
+            assert!(first.unwrap() == starting_game(PANDA_DECK, 7).0);
+        }
+        assert_eq!(
+            results[0],
+            play_seeded_game(PANDA_DECK, 7, &mut escape_bot_action, &limits)
+        );
+        assert!(results.iter().any(|r| *r != results[0]));
+    }
+
+    #[test]
+    fn tracking_cards() {
+        let limits = Limits::default();
+        let mut won = false;
+        for seed in 0..10 {
+            let result = play_seeded_game(PANDA_DECK, seed, &mut escape_bot_action, &limits);
+            let total = |f: fn(&CardCounts) -> u32| -> u32 {
+                enum_iterator::all::<Card>()
+                    .map(|card| f(result.cards.get(card)))
+                    .sum()
+            };
+            // At least the opening hand and a draw for each turn
+            assert!(total(|c| c.drawn) >= 3 + result.kill_turn as u32);
+            assert!(total(|c| c.played) >= total(|c| c.winning_line));
+            if result.won {
+                won = true;
+                assert!(total(|c| c.winning_line) > 0);
+            } else {
+                assert_eq!(total(|c| c.winning_line), 0);
+            }
+        }
+        assert!(won);
+    }
+}
@@ -0,0 +1,254 @@
+// The mean of some measurements, along with how precisely we know it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Summary {
+    pub n: usize,
+    pub mean: f64,
+    pub std_dev: f64,
+    pub std_error: f64,
+}
+
+// How many standard errors wide a 95% confidence interval is, on each side
+pub const Z_95: f64 = 1.96;
+
+impl Summary {
+    pub fn new(xs: &[f64]) -> Summary {
+        let n = xs.len();
+        let mean = xs.iter().sum::<f64>() / n.max(1) as f64;
+        let variance = if n > 1 {
+            xs.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() / (n - 1) as f64
+        } else {
+            0.0
+        };
+        let std_dev = variance.sqrt();
+        Summary {
+            n,
+            mean,
+            std_dev,
+            std_error: std_dev / (n.max(1) as f64).sqrt(),
+        }
+    }
+
+    // The 95% confidence interval for the mean
+    pub fn confidence_interval(&self) -> (f64, f64) {
+        (
+            self.mean - Z_95 * self.std_error,
+            self.mean + Z_95 * self.std_error,
+        )
+    }
+
+    // The two-sided p-value for the mean being zero, using a normal approximation
+    pub fn p_value(&self) -> f64 {
+        if self.std_error == 0.0 {
+            return if self.mean == 0.0 { 1.0 } else { 0.0 };
+        }
+        let z = (self.mean / self.std_error).abs();
+        2.0 * (1.0 - normal_cdf(z))
+    }
+}
+
+// How often something happened, out of n tries
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Proportion {
+    pub successes: usize,
+    pub n: usize,
+}
+
+impl Proportion {
+    pub fn rate(&self) -> f64 {
+        self.successes as f64 / self.n.max(1) as f64
+    }
+
+    // The 95% Wilson score interval, which stays within [0, 1] and still has some width
+    // when every try went the same way
+    pub fn wilson_interval(&self) -> (f64, f64) {
+        if self.n == 0 {
+            return (0.0, 1.0);
+        }
+        let n = self.n as f64;
+        let p = self.rate();
+        let z2 = Z_95 * Z_95;
+        let center = (p + z2 / (2.0 * n)) / (1.0 + z2 / n);
+        let half_width = Z_95 * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt() / (1.0 + z2 / n);
+        (
+            (center - half_width).max(0.0),
+            (center + half_width).min(1.0),
+        )
+    }
+}
+
+impl std::fmt::Display for Proportion {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.n == 0 {
+            return write!(f, "no games");
+        }
+        let (low, high) = self.wilson_interval();
+        write!(
+            f,
+            "{:.3} (95% CI {:.3} to {:.3}) over {} games",
+            self.rate(),
+            low,
+            high,
+            self.n
+        )
+    }
+}
+
+// The standard normal CDF, from the Abramowitz and Stegun approximation to erf
+pub fn normal_cdf(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.3275911 * x.abs() / 2f64.sqrt());
+    let poly = t
+        * (0.254829592
+            + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    let erf = 1.0 - poly * (-x * x / 2.0).exp();
+    if x >= 0.0 {
+        (1.0 + erf) / 2.0
+    } else {
+        (1.0 - erf) / 2.0
+    }
+}
+
+pub fn median(xs: &[f64]) -> f64 {
+    let mut sorted = xs.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+    let n = sorted.len();
+    match n {
+        0 => 0.0,
+        _ if n % 2 == 1 => sorted[n / 2],
+        _ => (sorted[n / 2 - 1] + sorted[n / 2]) / 2.0,
+    }
+}
+
+impl std::fmt::Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let (low, high) = self.confidence_interval();
+        write!(f, "{:.3} (95% CI {:.3} to {:.3})", self.mean, low, high)
+    }
+}
+
+// Summarizes a - b over paired measurements, like the same seeds played by two agents
+pub fn paired_difference(a: &[f64], b: &[f64]) -> Summary {
+    assert_eq!(a.len(), b.len());
+    let differences: Vec<f64> = a.iter().zip(b).map(|(x, y)| x - y).collect();
+    Summary::new(&differences)
+}
+
+// Predictions whose probabilities fell in [lower, upper), and how often they came true
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CalibrationBin {
+    pub lower: f64,
+    pub upper: f64,
+    pub count: usize,
+    pub predicted: f64, // the mean predicted probability
+    pub actual: f64,    // the fraction that came true
+}
+
+// Buckets (probability, outcome) pairs into equal-width bins, skipping empty bins.
+// A calibrated estimator has predicted close to actual in every bin.
+pub fn calibration(predictions: &[(f64, bool)], num_bins: usize) -> Vec<CalibrationBin> {
+    let mut sums = vec![(0, 0.0, 0); num_bins];
+    for (p, outcome) in predictions {
+        let i = ((p * num_bins as f64) as usize).min(num_bins - 1);
+        sums[i].0 += 1;
+        sums[i].1 += p;
+        sums[i].2 += *outcome as usize;
+    }
+    sums.iter()
+        .enumerate()
+        .filter(|(_, (count, _, _))| *count > 0)
+        .map(|(i, (count, predicted, hits))| CalibrationBin {
+            lower: i as f64 / num_bins as f64,
+            upper: (i + 1) as f64 / num_bins as f64,
+            count: *count,
+            predicted: predicted / *count as f64,
+            actual: *hits as f64 / *count as f64,
+        })
+        .collect()
+}
+
+// The mean squared error of probabilistic predictions, where lower is better
+pub fn brier_score(predictions: &[(f64, bool)]) -> f64 {
+    let total: f64 = predictions
+        .iter()
+        .map(|(p, outcome)| (p - *outcome as i32 as f64).powi(2))
+        .sum();
+    total / predictions.len().max(1) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary() {
+        let s = Summary::new(&[4.0, 6.0, 5.0, 5.0]);
+        assert_eq!(s.n, 4);
+        assert_eq!(s.mean, 5.0);
+        assert!((s.std_dev - (2.0f64 / 3.0).sqrt()).abs() < 1e-9);
+        let (low, high) = s.confidence_interval();
+        assert!(low < 5.0 && high > 5.0);
+    }
+
+    #[test]
+    fn pairing_removes_shared_noise() {
+        // b is always exactly one turn slower, despite a lot of noise between games
+        let a = [3.0, 9.0, 5.0, 7.0, 4.0];
+        let b: Vec<f64> = a.iter().map(|x| x + 1.0).collect();
+        let d = paired_difference(&a, &b);
+        assert_eq!(d.mean, -1.0);
+        assert_eq!(d.std_error, 0.0);
+    }
+
+    #[test]
+    fn p_values() {
+        assert!((normal_cdf(0.0) - 0.5).abs() < 1e-6);
+        assert!((normal_cdf(1.96) - 0.975).abs() < 1e-3);
+        assert!((normal_cdf(-1.96) - 0.025).abs() < 1e-3);
+        let noisy = Summary::new(&[1.0, -1.0, 2.0, -2.0]);
+        assert!(noisy.p_value() > 0.9);
+        let clear = Summary::new(&[1.0, 1.1, 0.9, 1.0, 1.05, 0.95]);
+        assert!(clear.p_value() < 0.001);
+    }
+
+    #[test]
+    fn wilson_intervals() {
+        let (low, high) = Proportion {
+            successes: 5,
+            n: 10,
+        }
+        .wilson_interval();
+        assert!((low - 0.2366).abs() < 1e-3 && (high - 0.7634).abs() < 1e-3);
+        let (low, high) = Proportion {
+            successes: 0,
+            n: 10,
+        }
+        .wilson_interval();
+        assert_eq!(low, 0.0);
+        assert!((high - 0.2775).abs() < 1e-3);
+        assert_eq!(Proportion { successes: 0, n: 0 }.to_string(), "no games");
+    }
+
+    #[test]
+    fn medians() {
+        assert_eq!(median(&[3.0, 1.0, 2.0]), 2.0);
+        assert_eq!(median(&[4.0, 1.0, 2.0, 3.0]), 2.5);
+    }
+
+    #[test]
+    fn calibration_bins() {
+        let predictions = [
+            (0.05, false),
+            (0.1, false),
+            (0.15, true),
+            (0.9, true),
+            (1.0, true),
+        ];
+        let bins = calibration(&predictions, 2);
+        assert_eq!(bins.len(), 2);
+        assert_eq!(bins[0].count, 3);
+        assert!((bins[0].predicted - 0.1).abs() < 1e-9);
+        assert!((bins[0].actual - 1.0 / 3.0).abs() < 1e-9);
+        assert_eq!(bins[1].count, 2);
+        assert_eq!(bins[1].actual, 1.0);
+        assert!(brier_score(&predictions) < brier_score(&[(0.5, true), (0.5, false)]));
+    }
+}